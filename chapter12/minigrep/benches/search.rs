@@ -0,0 +1,163 @@
+// 在合成的语料上比较几种搜索方式的耗时，运行：cargo bench
+// MINIGREP_BENCH_MB 设置大文件的大小（默认 64），MINIGREP_BENCH_RUNS 设置每项运行的次数（默认 5）
+//
+// 查询在语料中不存在，所以每种方式都要扫描全部内容，也不会有输出
+// （-q 找到匹配就会停止，但也不会并行，所以多文件时用 -l）
+// read_to_string 是第 12 章最初的做法：整个文件读进 String 再逐行查找
+use minigrep::{run, search, search_case_insensitive, Config};
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+const WORDS: [&str; 16] = [
+    "the",
+    "quick",
+    "brown",
+    "fox",
+    "jumps",
+    "over",
+    "lazy",
+    "dog",
+    "Rust",
+    "ownership",
+    "borrow",
+    "checker",
+    "lifetime",
+    "trait",
+    "closure",
+    "iterator",
+];
+const QUERY: &str = "zebrafish";
+const MANY_FILES: usize = 2000;
+
+fn main() {
+    let megabytes: usize = env_number("MINIGREP_BENCH_MB", 64);
+    let runs: usize = env_number("MINIGREP_BENCH_RUNS", 5);
+
+    let dir = env::temp_dir().join(format!("minigrep-bench-{}", std::process::id()));
+    let many = dir.join("many");
+    fs::create_dir_all(&many).unwrap();
+    let large = dir.join("large.txt");
+    fs::write(&large, corpus(megabytes << 20, 1)).unwrap();
+    for i in 0..MANY_FILES {
+        fs::write(many.join(format!("{}.txt", i)), corpus(4096, i as u64 + 2)).unwrap();
+    }
+    let large = large.to_str().unwrap();
+    let many = many.to_str().unwrap();
+
+    println!(
+        "{} MiB file, {} files of 4 KiB, best of {} runs",
+        megabytes, MANY_FILES, runs
+    );
+    println!();
+
+    group(
+        "literal",
+        runs,
+        || {
+            let contents = fs::read_to_string(large).unwrap();
+            assert!(search(QUERY, &contents).is_empty());
+        },
+        &[
+            ("stream (--no-mmap)", vec!["-q", "--no-mmap", QUERY, large]),
+            ("mmap", vec!["-q", QUERY, large]),
+        ],
+    );
+    group(
+        "case-insensitive",
+        runs,
+        || {
+            let contents = fs::read_to_string(large).unwrap();
+            assert!(search_case_insensitive(QUERY, &contents).is_empty());
+        },
+        &[
+            (
+                "stream (--no-mmap)",
+                vec!["-q", "-i", "--no-mmap", QUERY, large],
+            ),
+            ("mmap", vec!["-q", "-i", QUERY, large]),
+        ],
+    );
+    group(
+        "many files",
+        runs,
+        || {
+            for entry in fs::read_dir(many).unwrap() {
+                let contents = fs::read_to_string(entry.unwrap().path()).unwrap();
+                assert!(search(QUERY, &contents).is_empty());
+            }
+        },
+        &[
+            ("walk, 1 thread", vec!["-l", QUERY, many]),
+            ("walk, 4 threads", vec!["-l", "-j4", QUERY, many]),
+        ],
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// 先测 read_to_string 作为基准，再测 minigrep 的各种方式，打印耗时和相对基准的倍数
+fn group<F: FnMut()>(name: &str, runs: usize, baseline: F, cases: &[(&str, Vec<&str>)]) {
+    println!("{}:", name);
+    let base = best_of(runs, baseline);
+    report("read_to_string", base, base);
+    for (label, args) in cases {
+        let time = best_of(runs, || {
+            let args = std::iter::once("minigrep").chain(args.iter().copied());
+            let config = Config::new(args.map(String::from)).unwrap();
+            assert!(!run(config).unwrap());
+        });
+        report(label, time, base);
+    }
+    println!();
+}
+
+fn report(label: &str, time: Duration, base: Duration) {
+    println!(
+        "  {:<20} {:>9.2} ms  {:>5.2}x",
+        label,
+        time.as_secs_f64() * 1000.0,
+        base.as_secs_f64() / time.as_secs_f64()
+    );
+}
+
+fn best_of<F: FnMut()>(runs: usize, mut f: F) -> Duration {
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+// 由固定种子的线性同余生成器挑选单词组成的文本，每行 8 到 15 个单词
+fn corpus(size: usize, seed: u64) -> String {
+    let mut state = seed;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+    let mut text = String::with_capacity(size + 128);
+    while text.len() < size {
+        let words = 8 + next() % 8;
+        for i in 0..words {
+            if i > 0 {
+                text.push(' ');
+            }
+            text.push_str(WORDS[next() % WORDS.len()]);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn env_number(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,181 @@
+// 透明地读取压缩文件和 tar 包
+// 压缩格式按文件开头的魔数判断而不是看扩展名，所以轮转后改了名字的日志也能识别
+use crate::BINARY_PREFIX;
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+// tar 包的第一个文件头在 257 字节处有 "ustar" 标记
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    pub fn detect(head: &[u8]) -> Compression {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if head.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if head.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+            Compression::Xz
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+// 打开之后的输入：普通的文本流，或者需要逐个文件搜索的 tar 包
+pub enum Input<'a> {
+    Text(Box<dyn BufRead + 'a>),
+    Tar(tar::Archive<Box<dyn BufRead + 'a>>),
+}
+
+// 先解压，再看解压后的内容是不是 tar 包，所以 .tar.gz 之类的也能处理
+pub fn open<'a, R: BufRead + 'a>(reader: R) -> io::Result<Input<'a>> {
+    let mut reader = decompress(reader)?;
+
+    // 解压器一次交出的数据可能不够判断，先读出文件头，再把它接回流的前面
+    // 读出的长度至少是判断二进制文件需要的长度，这样接回去之后第一次 fill_buf 就能看到完整的开头
+    let mut head = Vec::with_capacity(BINARY_PREFIX);
+    reader
+        .by_ref()
+        .take(BINARY_PREFIX.max(TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+        .read_to_end(&mut head)?;
+    let is_tar = head.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC);
+    let reader: Box<dyn BufRead + 'a> = Box::new(Cursor::new(head).chain(reader));
+
+    Ok(if is_tar {
+        Input::Tar(tar::Archive::new(reader))
+    } else {
+        Input::Text(reader)
+    })
+}
+
+// 根据开头的魔数套上对应的解压器，不是压缩格式时原样返回
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    Ok(match Compression::detect(reader.fill_buf()?) {
+        Compression::None => Box::new(reader),
+        // 轮转日志经常是多个 gzip/bzip2 成员直接拼接起来的，要用 Multi 版本的解码器读完所有成员
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Compression::Xz => {
+            // lzma-rs 只提供一次性解压到 Write 的接口，只能先解压到内存里
+            let mut data = Vec::new();
+            lzma_rs::xz_decompress(&mut reader, &mut data).map_err(|e| invalid(e.to_string()))?;
+            Box::new(Cursor::new(data))
+        }
+        Compression::Zstd => {
+            let decoder =
+                ruzstd::StreamingDecoder::new(reader).map_err(|e| invalid(e.to_string()))?;
+            Box::new(BufReader::new(decoder))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TEXT: &str = "first line\nsecond line\n";
+
+    fn text(input: Input) -> String {
+        match input {
+            Input::Text(mut reader) => {
+                let mut text = String::new();
+                reader.read_to_string(&mut text).unwrap();
+                text
+            }
+            Input::Tar(_) => panic!("not a tar archive"),
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decompresses_by_magic_bytes() {
+        assert_eq!(text(open(TEXT.as_bytes()).unwrap()), TEXT);
+
+        // 两个 gzip 成员拼接在一起
+        let mut gz = gzip(b"first line\n");
+        gz.extend(gzip(b"second line\n"));
+        assert_eq!(text(open(&gz[..]).unwrap()), TEXT);
+
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+        let bz2 = encoder.finish().unwrap();
+        assert_eq!(text(open(&bz2[..]).unwrap()), TEXT);
+
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut TEXT.as_bytes(), &mut xz).unwrap();
+        assert_eq!(text(open(&xz[..]).unwrap()), TEXT);
+
+        // 手工构造的 zstd 帧：单段帧头，一个未压缩的 raw block
+        let mut zst = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, TEXT.len() as u8];
+        let block = 1 | (TEXT.len() << 3);
+        zst.extend(&[block as u8, (block >> 8) as u8, (block >> 16) as u8]);
+        zst.extend(TEXT.as_bytes());
+        assert_eq!(text(open(&zst[..]).unwrap()), TEXT);
+    }
+
+    #[test]
+    fn binary_detection_sees_whole_prefix() {
+        let argv = vec!["minigrep", "x"];
+        let config = crate::Config::new(argv.into_iter().map(String::from)).unwrap();
+        let mut data = b"x\n".repeat(500);
+        data.push(0);
+        data.extend(b"x\n");
+
+        for data in [data.clone(), gzip(&data)] {
+            let reader = match open(&data[..]).unwrap() {
+                Input::Text(reader) => reader,
+                Input::Tar(_) => panic!("not a tar archive"),
+            };
+            let mut printer = crate::printer::Printer::new(Vec::new(), &config, false);
+            let path = std::path::Path::new("data");
+            let count = crate::search_stream(&config, path, reader, (0, 0), &mut printer).unwrap();
+            assert_eq!(count, 0);
+            assert!(printer.into_inner().is_empty());
+        }
+    }
+
+    #[test]
+    fn finds_tar_entries() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(TEXT.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "logs/app.log", TEXT.as_bytes())
+            .unwrap();
+        let tar = gzip(&builder.into_inner().unwrap());
+
+        let mut archive = match open(&tar[..]).unwrap() {
+            Input::Tar(archive) => archive,
+            Input::Text(_) => panic!("tar archive not detected"),
+        };
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap().to_str(), Some("logs/app.log"));
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, TEXT);
+    }
+}
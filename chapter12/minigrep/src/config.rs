@@ -0,0 +1,1172 @@
+// 命令行参数解析
+// 支持短选项（-i）、长选项（--ignore-case）、合并的短选项（-inv）、--name=value 写法，
+// 以及用 -- 结束选项解析，之后的参数都当作位置参数
+use crate::encoding::Encoding;
+use crate::glob::{Glob, GlobError};
+use crate::literal::Literal;
+use crate::pattern::Pattern;
+use crate::profile::Profile;
+use crate::searcher::Searcher;
+use crate::walk::Filter;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH...]
+       minigrep [OPTIONS] -e PATTERN... [-f FILE...] [PATH...]
+
+Search for QUERY in each PATH. Directories are searched recursively.
+With no PATH, or when PATH is -, read standard input.
+With -e or -f, a line matches if any of the patterns matches and every
+positional argument is a PATH.
+
+Options:
+  -i, --ignore-case         Case insensitive search (default if CASE_INSENSITIVE is set)
+  -s, --case-sensitive      Case sensitive search, overrides CASE_INSENSITIVE
+  -e, --regexp PATTERN      Search for PATTERN; can be given more than once
+  -f, --file FILE           Read patterns from FILE, one per line
+  -E, --regex               Treat QUERY as a regular expression
+  -w, --word-regexp         Only match whole words
+      --fuzzy K             Match lines containing QUERY within K edits
+                            (Levenshtein); lines in each file are listed
+                            closest first, with the closest part highlighted
+      --field NAME          Parse each line as a record and match only in
+                            field NAME: a CSV column named in the header
+                            row (or its 1-based number), or a JSON Lines
+                            path such as user.email; prints whole records
+  -v, --invert-match        Select non-matching lines
+  -n, --line-number         Prefix each line with its line number
+  -b, --byte-offset         Prefix each line with its 0-based byte offset
+  -A, --after-context NUM   Print NUM lines of trailing context
+  -B, --before-context NUM  Print NUM lines of leading context
+  -C, --context NUM         Print NUM lines of leading and trailing context
+  -c, --count               Print only a count of matching lines per file
+  -l, --files-with-matches  Print only names of files with matches
+  -L, --files-without-match Print only names of files without matches
+  -m, --max-count NUM       Stop reading a file after NUM matching lines
+  -q, --quiet               Print nothing, exit 0 as soon as a match is found
+      --replace TEXT        Replace each match with TEXT and print a diff;
+                            $1 or ${name} refer to capture groups with -E
+      --write               With --replace, rewrite the files in place
+      --backup SUFFIX       With --write, keep the original as PATH + SUFFIX
+      --encoding ENC        Decode input as ENC before matching: auto, utf-8,
+                            utf-16le, utf-16be, latin1 or gbk (default auto,
+                            which only honours a BOM and otherwise searches
+                            raw bytes, showing invalid UTF-8 as U+FFFD)
+      --color WHEN          Highlight matches: auto, always or never (default auto)
+      --json                Print results as JSON Lines
+      --watch               Keep running and search files again when they
+                            change; appended lines are searched as they
+                            arrive, like tail -f | grep
+      --watch-interval MS   How often --watch checks for changes (default 1000)
+  -j, --threads NUM         Search NUM files in parallel (default 1)
+      --no-mmap             Never memory-map large files, always read them
+      --include GLOB        Only search files whose name matches GLOB
+      --exclude GLOB        Skip files and directories whose name matches GLOB
+  -t, --type TYPE           Only search files of TYPE, e.g. rust or py
+  -T, --type-not TYPE       Skip files of TYPE
+      --no-config           Ignore config files
+      --debug-config        Print the config files read and the merged
+                            options, then exit
+  -h, --help                Print this help and exit
+  -V, --version             Print version and exit
+      --                    Treat all following arguments as QUERY or PATH
+
+Exit status is 0 if a line is selected, 1 if no lines were selected,
+and 2 if an error occurred (with -q, 0 wins if a line is selected).
+
+Default options are read from ~/.config/minigrep/config.toml (or the file
+named by MINIGREP_CONFIG) and then from the nearest .minigreprc in the
+current directory or its parents; options on the command line win.
+Both files may set flags, ignore, color and [types]:
+
+    flags = [\"--line-number\"]
+    ignore = [\"target\"]
+    color = \"auto\"
+
+    [types]
+    web = [\"*.html\", \"*.css\"]";
+
+// 短选项到长选项的映射，解析时统一按长选项名处理
+const SHORT_OPTIONS: [(char, &str); 22] = [
+    ('i', "ignore-case"),
+    ('s', "case-sensitive"),
+    ('e', "regexp"),
+    ('f', "file"),
+    ('E', "regex"),
+    ('w', "word-regexp"),
+    ('v', "invert-match"),
+    ('n', "line-number"),
+    ('b', "byte-offset"),
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('c', "count"),
+    ('l', "files-with-matches"),
+    ('L', "files-without-match"),
+    ('m', "max-count"),
+    ('q', "quiet"),
+    ('j', "threads"),
+    ('t', "type"),
+    ('T', "type-not"),
+    ('h', "help"),
+    ('V', "version"),
+];
+
+// parse_args 的结果：按顺序排列的（长选项名，参数）和位置参数
+pub(crate) type ParsedArgs = (Vec<(String, Option<String>)>, Vec<String>);
+
+// 需要带参数的长选项
+const VALUE_OPTIONS: [&str; 18] = [
+    "regexp",
+    "file",
+    "include",
+    "exclude",
+    "after-context",
+    "before-context",
+    "context",
+    "threads",
+    "color",
+    "max-count",
+    "replace",
+    "backup",
+    "encoding",
+    "fuzzy",
+    "field",
+    "type",
+    "type-not",
+    "watch-interval",
+];
+
+// --color 的取值，auto 时只有标准输出是终端并且没有设置 NO_COLOR 才输出颜色
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    // 命令行上明确给出的 always/never 优先于 NO_COLOR（见 https://no-color.org）
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // --help 和 --version 不是真正的错误，但同样需要提前结束解析，由 main 打印后以 0 退出
+    Help,
+    Version,
+    MissingQuery,
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue(String, String),
+    // 两个不能同时使用的选项
+    Conflict(String, String),
+    // 第一个选项只能和第二个选项一起使用
+    Requires(String, String),
+    // --debug-config 的输出，和 --help 一样由 main 打印后以 0 退出
+    DebugConfig(String),
+    // -f 指定的模式文件读取失败
+    PatternFile(String, io::Error),
+    // 配置文件读取或解析失败
+    ConfigFile(PathBuf, String),
+    Glob(GlobError),
+    Regex(regex::Error),
+    Automaton(aho_corasick::BuildError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Help => write!(f, "{}", USAGE),
+            ConfigError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+            ConfigError::MissingQuery => write!(f, "Didn't get a query string"),
+            ConfigError::UnknownOption(name) => write!(f, "Unknown option: {}", name),
+            ConfigError::MissingValue(name) => write!(f, "Missing value for --{}", name),
+            ConfigError::UnexpectedValue(name) => {
+                write!(f, "Option --{} doesn't take a value", name)
+            }
+            ConfigError::InvalidValue(name, value) => {
+                write!(f, "Invalid value for --{}: {}", name, value)
+            }
+            ConfigError::Conflict(a, b) => {
+                write!(f, "Options --{} and --{} can't be used together", a, b)
+            }
+            ConfigError::Requires(a, b) => write!(f, "Option --{} requires --{}", a, b),
+            ConfigError::DebugConfig(text) => write!(f, "{}", text),
+            ConfigError::PatternFile(path, e) => write!(f, "{}: {}", path, e),
+            ConfigError::ConfigFile(path, message) => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            ConfigError::Glob(e) => write!(f, "{}", e),
+            ConfigError::Regex(e) => write!(f, "{}", e),
+            ConfigError::Automaton(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::PatternFile(_, e) => Some(e),
+            ConfigError::Glob(e) => Some(e),
+            ConfigError::Regex(e) => Some(e),
+            ConfigError::Automaton(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<GlobError> for ConfigError {
+    fn from(e: GlobError) -> ConfigError {
+        ConfigError::Glob(e)
+    }
+}
+
+impl From<regex::Error> for ConfigError {
+    fn from(e: regex::Error) -> ConfigError {
+        ConfigError::Regex(e)
+    }
+}
+
+impl From<aho_corasick::BuildError> for ConfigError {
+    fn from(e: aho_corasick::BuildError) -> ConfigError {
+        ConfigError::Automaton(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    // 使用 -e/-f 时是所有模式用换行连接起来的结果，只用于显示
+    pub query: String,
+    // 按 -e/-f 出现顺序排列的所有模式，匹配结果中的模式下标指向这里
+    pub patterns: Vec<String>,
+    // 可以是文件也可以是目录，目录会被递归搜索；没有给出时是 ["-"]，即标准输入
+    pub paths: Vec<String>,
+    pub case_sensitive: bool,
+    // 传入 --regex 时模式会被编译成正则表达式，否则做普通的子串匹配，多个字面量模式使用 Aho-Corasick 自动机
+    pub matcher: Pattern,
+    // --include/--exclude 以及遍历目录时的过滤条件
+    pub filter: Filter,
+    // --type/--type-not 可以使用的类型，来自内置类型和配置文件
+    pub types: BTreeMap<String, Vec<String>>,
+    pub word: bool,
+    // --fuzzy 允许的最大编辑距离
+    pub fuzzy: Option<usize>,
+    // --field 指定的 CSV 列名或 JSON 路径
+    pub field: Option<String>,
+    pub invert: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    // -A/-B/-C 指定的上下文行数
+    pub before_context: usize,
+    pub after_context: usize,
+    pub count: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    // -m：每个文件最多匹配的行数
+    pub max_count: Option<usize>,
+    // -q：不输出任何内容，只用退出码表示是否匹配
+    pub quiet: bool,
+    // --replace 的替换文本；--write 时直接改写文件，backup 是备份文件名的后缀
+    pub replace: Option<String>,
+    pub write: bool,
+    pub backup: Option<String>,
+    pub color: ColorChoice,
+    pub encoding: Encoding,
+    // 以 JSON Lines 格式输出
+    pub json: bool,
+    // 并行搜索的线程数，1 表示串行
+    pub threads: usize,
+    // 大的普通文件是否用内存映射搜索，--no-mmap 关闭
+    pub mmap: bool,
+    pub watch: bool,
+    // --watch 两次检查之间的间隔
+    pub watch_interval: Duration,
+}
+
+impl Config {
+    // 参数用泛型迭代器而不是 std::env::Args，这样测试里也可以直接传入 Vec<String>.into_iter()
+    // 不读配置文件，只有内置的 --type 类型
+    pub fn new<T>(args: T) -> Result<Config, ConfigError>
+    where
+        T: Iterator<Item = String>,
+    {
+        Config::with_profile(args, Profile::builtin())
+    }
+
+    // main 使用：先读配置文件再解析命令行，命令行上有 --no-config 时和 new 一样
+    pub fn load<T>(args: T) -> Result<Config, ConfigError>
+    where
+        T: Iterator<Item = String>,
+    {
+        let args: Vec<String> = args.collect();
+        let no_config = args
+            .iter()
+            .skip(1)
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "--no-config");
+        let profile = if no_config {
+            Profile::builtin()
+        } else {
+            Profile::load()?
+        };
+        Config::with_profile(args.into_iter(), profile)
+    }
+
+    // 配置文件是优先级较低的一层：配置文件和命令行分别解析，命令行上的选项后应用，覆盖配置文件中的同名选项
+    // 配置文件中的选项和命令行上的选项冲突时忽略配置文件中的那个，同一层里的冲突仍然是错误
+    pub fn with_profile<T>(mut args: T, profile: Profile) -> Result<Config, ConfigError>
+    where
+        T: Iterator<Item = String>,
+    {
+        args.next();
+        let cli: Vec<String> = args.collect();
+        let (cli_options, positional) = parse_args(&cli)?;
+        // merge 保证了配置文件里只有选项，带参数的选项的参数也在同一个配置文件里
+        let (profile_options, _) = parse_args(&profile.args)?;
+
+        let on_cli = |name: &str| {
+            let related = related_options(name);
+            cli_options
+                .iter()
+                .any(|(option, _)| related.contains(&option.as_str()))
+        };
+        // 冲突时会多次调用 build，-f 的文件只在第一次读，之后用读到的内容（-f - 第二次读 stdin 只会得到空内容）
+        let mut pattern_files = BTreeMap::new();
+        let mut ignored: Vec<String> = Vec::new();
+        loop {
+            let options = profile_options
+                .iter()
+                .filter(|(name, _)| !ignored.contains(name))
+                .chain(&cli_options);
+            let (a, b) =
+                match Config::build(options, &positional, &profile, &cli, &mut pattern_files) {
+                    Err(ConfigError::Conflict(a, b)) => (a, b),
+                    result => return result,
+                };
+            let loser = match (on_cli(&a), on_cli(&b)) {
+                (true, false) => &b,
+                (false, true) => &a,
+                _ => return Err(ConfigError::Conflict(a, b)),
+            };
+            let related = related_options(loser);
+            let from_profile = profile_options
+                .iter()
+                .any(|(name, _)| related.contains(&name.as_str()) && !ignored.contains(name));
+            if !from_profile {
+                return Err(ConfigError::Conflict(a, b));
+            }
+            ignored.extend(related.iter().map(|name| name.to_string()));
+        }
+    }
+
+    // 依次应用 options，再用位置参数补上查询和路径，最后检查选项之间的冲突
+    // pattern_files 缓存读过的 -f 文件，按路径保存其中的每一行
+    fn build<'a, I>(
+        options: I,
+        positional: &[String],
+        profile: &Profile,
+        cli: &[String],
+        pattern_files: &mut BTreeMap<String, Vec<String>>,
+    ) -> Result<Config, ConfigError>
+    where
+        I: Iterator<Item = &'a (String, Option<String>)>,
+    {
+        // read var from env
+        // 如果 CASE_INSENSITIVE 被设置为任何值，is_err 会返回 false 并将进行大小写不敏感搜索
+        // 这里我们只关心 CASE_INSENSITIVE 是否被设置了而不关心所设置的值，所以使用了 is_err 而不是 unwrap/expect
+        // 环境变量只是默认值，命令行上的 -i/-s 会覆盖它
+        let mut config = Config {
+            query: String::new(),
+            patterns: Vec::new(),
+            paths: Vec::new(),
+            case_sensitive: env::var("CASE_INSENSITIVE").is_err(),
+            // 占位，解析完所有选项后再构造
+            matcher: Pattern::Literal(Literal::new("", true, false)),
+            filter: Filter::default(),
+            types: profile.types.clone(),
+            word: false,
+            fuzzy: None,
+            field: None,
+            invert: false,
+            line_number: false,
+            byte_offset: false,
+            before_context: 0,
+            after_context: 0,
+            count: false,
+            files_with_matches: false,
+            files_without_match: false,
+            max_count: None,
+            quiet: false,
+            replace: None,
+            write: false,
+            backup: None,
+            color: ColorChoice::Auto,
+            encoding: Encoding::Auto,
+            json: false,
+            threads: 1,
+            mmap: true,
+            watch: false,
+            watch_interval: Duration::from_millis(1000),
+        };
+        let mut use_regex = false;
+        // 是否给出过 -e/-f；-f 指向空文件时 patterns 为空，但同样不再从位置参数中取查询
+        let mut explicit = false;
+        let mut debug_config = false;
+        for (name, value) in options {
+            if name == "debug-config" {
+                debug_config = true;
+                continue;
+            }
+            if name == "file" {
+                explicit = true;
+                let path = value.clone().unwrap();
+                if !pattern_files.contains_key(&path) {
+                    let lines = read_patterns(&path)?;
+                    pattern_files.insert(path.clone(), lines);
+                }
+                config.patterns.extend_from_slice(&pattern_files[&path]);
+                continue;
+            }
+            config.set(name, value.clone(), &mut use_regex, &mut explicit)?;
+        }
+
+        let mut positional = positional.iter().cloned();
+        if explicit {
+            config.query = config.patterns.join("\n");
+        } else {
+            config.query = match positional.next() {
+                Some(query) => query,
+                // 只看配置时可以不给查询
+                None if debug_config => String::new(),
+                None => return Err(ConfigError::MissingQuery),
+            };
+            config.patterns.push(config.query.clone());
+        }
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            config.paths.push(String::from("-"));
+        }
+
+        // JSON 和 --replace 输出的都是每一条匹配，和只输出统计或文件名的模式冲突
+        let replace = config.replace.is_some();
+        let fuzzy = config.fuzzy.is_some();
+        let conflicts = [
+            (config.json, "json", config.count, "count"),
+            (
+                config.json,
+                "json",
+                config.files_with_matches,
+                "files-with-matches",
+            ),
+            (
+                config.json,
+                "json",
+                config.files_without_match,
+                "files-without-match",
+            ),
+            (config.json, "json", replace, "replace"),
+            (replace, "replace", config.count, "count"),
+            (
+                replace,
+                "replace",
+                config.files_with_matches,
+                "files-with-matches",
+            ),
+            (
+                replace,
+                "replace",
+                config.files_without_match,
+                "files-without-match",
+            ),
+            (replace, "replace", config.invert, "invert-match"),
+            (replace, "replace", config.quiet, "quiet"),
+            // 改写文件时没法按原来的编码写回去
+            (
+                replace,
+                "replace",
+                config.encoding != Encoding::Auto,
+                "encoding",
+            ),
+            (
+                config.files_with_matches,
+                "files-with-matches",
+                config.files_without_match,
+                "files-without-match",
+            ),
+            // 近似匹配只支持单个普通查询，而且结果按距离排序，没有上下文可言
+            (fuzzy, "fuzzy", use_regex, "regex"),
+            (fuzzy, "fuzzy", config.patterns.len() > 1, "regexp"),
+            (fuzzy, "fuzzy", config.word, "word-regexp"),
+            (fuzzy, "fuzzy", config.invert, "invert-match"),
+            (fuzzy, "fuzzy", replace, "replace"),
+            (
+                fuzzy,
+                "fuzzy",
+                config.before_context > 0 || config.after_context > 0,
+                "context",
+            ),
+            // --watch 会不断输出新的匹配行，没有最终的统计或文件列表可言
+            (config.watch, "watch", replace, "replace"),
+            (config.watch, "watch", config.count, "count"),
+            (
+                config.watch,
+                "watch",
+                config.files_with_matches,
+                "files-with-matches",
+            ),
+            (
+                config.watch,
+                "watch",
+                config.files_without_match,
+                "files-without-match",
+            ),
+            (config.watch, "watch", config.quiet, "quiet"),
+            // 改写时替换的是整行里的匹配，不知道字段边界
+            (config.field.is_some(), "field", replace, "replace"),
+            (config.field.is_some(), "field", fuzzy, "fuzzy"),
+        ];
+        if let Some((_, a, _, b)) = conflicts.iter().find(|(a, _, b, _)| *a && *b) {
+            return Err(ConfigError::Conflict(a.to_string(), b.to_string()));
+        }
+        if config.write && !replace {
+            return Err(ConfigError::Requires(
+                "write".to_string(),
+                "replace".to_string(),
+            ));
+        }
+        if config.backup.is_some() && !config.write {
+            return Err(ConfigError::Requires(
+                "backup".to_string(),
+                "write".to_string(),
+            ));
+        }
+
+        // 正则在这里编译一次，语法错误直接作为 Err 返回给调用方而不是在 run 里 panic
+        config.matcher = Pattern::new(
+            &config.patterns,
+            use_regex,
+            config.case_sensitive,
+            config.word,
+            config.fuzzy,
+        )?;
+
+        if debug_config {
+            return Err(ConfigError::DebugConfig(config.describe(profile, cli)));
+        }
+        Ok(config)
+    }
+
+    // --debug-config 的输出：读取了哪些配置文件、合并后的参数以及最终生效的设置
+    fn describe(&self, profile: &Profile, cli: &[String]) -> String {
+        let globs = |globs: &[Glob]| {
+            let globs: Vec<&str> = globs.iter().map(|g| g.as_str()).collect();
+            format!("{:?}", globs)
+        };
+        let mut lines = vec![String::from("config files:")];
+        if profile.sources.is_empty() {
+            lines.push(String::from("  (none)"));
+        }
+        for path in &profile.sources {
+            lines.push(format!("  {}", path.display()));
+        }
+        lines.push(format!("from config files: {:?}", profile.args));
+        lines.push(format!("from command line: {:?}", cli));
+        lines.push(format!("patterns: {:?}", self.patterns));
+        lines.push(format!("paths: {:?}", self.paths));
+        lines.push(format!("case sensitive: {}", self.case_sensitive));
+        lines.push(format!("include: {}", globs(&self.filter.include)));
+        lines.push(format!("exclude: {}", globs(&self.filter.exclude)));
+        lines.push(format!("color: {:?}", self.color));
+        lines.push(format!("encoding: {:?}", self.encoding));
+        lines.push(format!("threads: {}", self.threads));
+        lines.push(format!(
+            "line number: {}, byte offset: {}, context: -B {} -A {}",
+            self.line_number, self.byte_offset, self.before_context, self.after_context
+        ));
+        lines.push(String::from("types:"));
+        for (name, globs) in &self.types {
+            lines.push(format!("  {}: {}", name, globs.join(" ")));
+        }
+        lines.join("\n")
+    }
+
+    // 按 -A/-B/-C、-m 和 -v 配置好的 Searcher
+    pub fn searcher(&self) -> Searcher {
+        Searcher::new()
+            .before_context(self.before_context)
+            .after_context(self.after_context)
+            .max_count(self.max_count)
+            .invert(self.invert)
+    }
+
+    fn set(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+        use_regex: &mut bool,
+        explicit: &mut bool,
+    ) -> Result<(), ConfigError> {
+        match name {
+            "ignore-case" => self.case_sensitive = false,
+            "case-sensitive" => self.case_sensitive = true,
+            "regexp" => {
+                *explicit = true;
+                self.patterns.push(value.unwrap());
+            }
+            "regex" => *use_regex = true,
+            "word-regexp" => self.word = true,
+            "invert-match" => self.invert = true,
+            "line-number" => self.line_number = true,
+            "byte-offset" => self.byte_offset = true,
+            // -C 同时设置两边，之后出现的 -A/-B 可以单独覆盖其中一边
+            "after-context" => self.after_context = parse_number(name, value)?,
+            "before-context" => self.before_context = parse_number(name, value)?,
+            "context" => {
+                self.after_context = parse_number(name, value)?;
+                self.before_context = self.after_context;
+            }
+            "count" => self.count = true,
+            "files-with-matches" => self.files_with_matches = true,
+            "files-without-match" => self.files_without_match = true,
+            "max-count" => self.max_count = Some(parse_number(name, value)?),
+            "fuzzy" => self.fuzzy = Some(parse_number(name, value)?),
+            "field" => self.field = value,
+            "quiet" => self.quiet = true,
+            "replace" => self.replace = value,
+            "write" => self.write = true,
+            "backup" => self.backup = value,
+            "color" => {
+                let value = value.unwrap();
+                self.color = match value.as_str() {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return Err(ConfigError::InvalidValue(name.to_string(), value)),
+                };
+            }
+            "encoding" => {
+                let value = value.unwrap();
+                self.encoding = Encoding::from_label(&value)
+                    .ok_or_else(|| ConfigError::InvalidValue(name.to_string(), value))?;
+            }
+            "json" => self.json = true,
+            "no-mmap" => self.mmap = false,
+            "watch" => self.watch = true,
+            "watch-interval" => {
+                let value = value.unwrap();
+                self.watch_interval = match value.parse() {
+                    Ok(ms) if ms > 0 => Duration::from_millis(ms),
+                    _ => return Err(ConfigError::InvalidValue(name.to_string(), value)),
+                };
+            }
+            "threads" => {
+                let value = value.unwrap();
+                self.threads = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(ConfigError::InvalidValue(name.to_string(), value)),
+                };
+            }
+            "help" => return Err(ConfigError::Help),
+            "version" => return Err(ConfigError::Version),
+            // VALUE_OPTIONS 中的选项一定带着 value
+            "include" => self.filter.include.push(Glob::new(&value.unwrap())?),
+            "exclude" => self.filter.exclude.push(Glob::new(&value.unwrap())?),
+            "type" | "type-not" => {
+                let value = value.unwrap();
+                let globs = self
+                    .types
+                    .get(&value)
+                    .ok_or_else(|| ConfigError::InvalidValue(name.to_string(), value.clone()))?;
+                for glob in globs {
+                    let glob = Glob::new(glob)?;
+                    if name == "type" {
+                        self.filter.include.push(glob);
+                    } else {
+                        self.filter.exclude.push(glob);
+                    }
+                }
+            }
+            // 在 load 里已经处理过了
+            "no-config" => {}
+            _ => return Err(ConfigError::UnknownOption(format!("--{}", name))),
+        }
+        Ok(())
+    }
+}
+
+// 把参数拆成按顺序排列的（长选项名，参数）和位置参数
+// 不检查选项名是否存在，这由 Config::set 负责；带参数的选项缺少参数时返回 MissingValue
+pub(crate) fn parse_args(args: &[String]) -> Result<ParsedArgs, ConfigError> {
+    let mut options = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.find('=') {
+                Some(i) => (&long[..i], Some(long[i + 1..].to_string())),
+                None => (long, None),
+            };
+            let value = if VALUE_OPTIONS.contains(&name) {
+                match inline {
+                    Some(value) => Some(value),
+                    None => Some(
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(name.to_string()))?,
+                    ),
+                }
+            } else if inline.is_some() {
+                return Err(ConfigError::UnexpectedValue(name.to_string()));
+            } else {
+                None
+            };
+            options.push((name.to_string(), value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // 合并的短选项，例如 -inv 等价于 -i -n -v
+            // 带参数的短选项会把剩下的部分当作参数，例如 -nA3；剩下为空时取下一个参数
+            for (i, c) in arg.char_indices().skip(1) {
+                let name = SHORT_OPTIONS
+                    .iter()
+                    .find(|(short, _)| *short == c)
+                    .map(|(_, long)| *long)
+                    .ok_or_else(|| ConfigError::UnknownOption(format!("-{}", c)))?;
+                if VALUE_OPTIONS.contains(&name) {
+                    let rest = &arg[i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(name.to_string()))?
+                    } else {
+                        rest.to_string()
+                    };
+                    options.push((name.to_string(), Some(value)));
+                    break;
+                }
+                options.push((name.to_string(), None));
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+    Ok((options, positional))
+}
+
+// 冲突表中的名字对应的所有选项，配置文件中的选项被命令行覆盖时要一起忽略
+fn related_options(name: &str) -> Vec<&str> {
+    match name {
+        "context" => vec!["context", "after-context", "before-context"],
+        "regexp" => vec!["regexp", "file"],
+        name => vec![name],
+    }
+}
+
+// -f 的文件每行一个模式，- 表示从标准输入读
+fn read_patterns(path: &str) -> Result<Vec<String>, ConfigError> {
+    let contents = if path == crate::STDIN_PATH {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    };
+    let contents = contents.map_err(|e| ConfigError::PatternFile(path.to_string(), e))?;
+    Ok(contents.lines().map(String::from).collect())
+}
+
+fn parse_number(name: &str, value: Option<String>) -> Result<usize, ConfigError> {
+    let value = value.unwrap();
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> std::vec::IntoIter<String> {
+        let mut args = vec![String::from("/path/to/script")];
+        args.extend(list.iter().map(|s| s.to_string()));
+        args.into_iter()
+    }
+
+    #[test]
+    fn not_enough_args() {
+        assert!(matches!(
+            Config::new(args(&[])),
+            Err(ConfigError::MissingQuery)
+        ));
+        // 没有文件名时读取标准输入
+        assert_eq!(Config::new(args(&["the"])).unwrap().paths, vec!["-"]);
+    }
+
+    #[test]
+    fn regex_flag() {
+        let config = Config::new(args(&["--regex", "^(To|How) \\w+", "poem.txt"])).unwrap();
+        assert_eq!(config.paths, vec!["poem.txt"]);
+        assert!(matches!(config.matcher, Pattern::Regex { .. }));
+    }
+
+    #[test]
+    fn invalid_regex_is_error() {
+        assert!(matches!(
+            Config::new(args(&["-E", "(unclosed", "poem.txt"])),
+            Err(ConfigError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn paths_and_globs() {
+        let config = Config::new(args(&[
+            "--include=*.rs",
+            "fn",
+            "src",
+            "--exclude",
+            "target",
+            "Cargo.toml",
+        ]))
+        .unwrap();
+        assert_eq!(config.query, "fn");
+        assert_eq!(config.paths, vec!["src", "Cargo.toml"]);
+        assert_eq!(config.filter.include[0].as_str(), "*.rs");
+        assert_eq!(config.filter.exclude[0].as_str(), "target");
+        assert!(Config::new(args(&["fn", "src"])).unwrap().mmap);
+        assert!(!Config::new(args(&["--no-mmap", "fn", "src"])).unwrap().mmap);
+        assert!(matches!(
+            Config::new(args(&["fn", "src", "--include"])),
+            Err(ConfigError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = Config::new(args(&["-ivn", "-c", "the", "poem.txt"])).unwrap();
+        assert!(!config.case_sensitive);
+        assert!(config.invert && config.line_number && config.count);
+        assert!(!config.files_with_matches);
+
+        // 后出现的选项覆盖先出现的
+        let config = Config::new(args(&["-is", "the", "poem.txt"])).unwrap();
+        assert!(config.case_sensitive);
+    }
+
+    #[test]
+    fn context_options() {
+        let config = Config::new(args(&["-nC2", "-A", "5", "the", "poem.txt"])).unwrap();
+        assert!(config.line_number);
+        assert_eq!((config.before_context, config.after_context), (2, 5));
+        assert!(matches!(
+            Config::new(args(&["--context=many", "the", "poem.txt"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn threads_option() {
+        assert_eq!(Config::new(args(&["the", "src"])).unwrap().threads, 1);
+        assert_eq!(
+            Config::new(args(&["-j4", "the", "src"])).unwrap().threads,
+            4
+        );
+        assert!(matches!(
+            Config::new(args(&["-j", "0", "the", "src"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn color_option() {
+        let config = Config::new(args(&["--color=always", "the"])).unwrap();
+        assert_eq!(config.color, ColorChoice::Always);
+        assert!(config.color.enabled());
+        // 测试的标准输出不是终端
+        assert!(!ColorChoice::Auto.enabled());
+        assert!(matches!(
+            Config::new(args(&["--color", "sometimes", "the"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn output_modes() {
+        let config = Config::new(args(&["-qm2", "the", "src"])).unwrap();
+        assert!(config.quiet);
+        assert_eq!(config.max_count, Some(2));
+        assert!(
+            Config::new(args(&["-L", "the"]))
+                .unwrap()
+                .files_without_match
+        );
+        assert!(matches!(
+            Config::new(args(&["-lL", "the", "src"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+    }
+
+    #[test]
+    fn replace_options() {
+        let config = Config::new(args(&[
+            "-E",
+            "--replace=$2 $1",
+            "--write",
+            "--backup",
+            ".orig",
+            r"(\w+) (\w+)",
+            "src",
+        ]))
+        .unwrap();
+        assert_eq!(config.replace.as_deref(), Some("$2 $1"));
+        assert!(config.write);
+        assert_eq!(config.backup.as_deref(), Some(".orig"));
+        assert!(matches!(
+            Config::new(args(&["--write", "the", "src"])),
+            Err(ConfigError::Requires(_, _))
+        ));
+        assert!(matches!(
+            Config::new(args(&["--replace", "x", "-v", "the", "src"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+    }
+
+    #[test]
+    fn encoding_option() {
+        let config = Config::new(args(&["--encoding=GBK", "中文"])).unwrap();
+        assert_eq!(config.encoding, Encoding::Gbk);
+        assert!(matches!(
+            Config::new(args(&["--encoding", "ebcdic", "the"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn fuzzy_option() {
+        let config = Config::new(args(&["--fuzzy=2", "recieve", "src"])).unwrap();
+        assert_eq!(config.fuzzy, Some(2));
+        assert!(matches!(config.matcher, Pattern::Fuzzy(_)));
+        assert!(matches!(
+            Config::new(args(&["--fuzzy", "1", "-E", "a+", "src"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+    }
+
+    #[test]
+    fn field_option() {
+        let config = Config::new(args(&["--field", "user.email", "@example", "log"])).unwrap();
+        assert_eq!(config.field.as_deref(), Some("user.email"));
+        assert!(matches!(
+            Config::new(args(&["--field=email", "--replace", "x", "a", "log"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+    }
+
+    #[test]
+    fn type_filters() {
+        let config = Config::new(args(&["-t", "rust", "--type-not=toml", "fn", "."])).unwrap();
+        assert_eq!(config.filter.include[0].as_str(), "*.rs");
+        assert_eq!(config.filter.exclude[0].as_str(), "*.toml");
+        assert!(matches!(
+            Config::new(args(&["--type", "cobol", "fn", "."])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn command_line_overrides_profile() {
+        let mut profile = Profile::builtin();
+        profile
+            .merge(
+                std::path::Path::new(".minigreprc"),
+                "flags = [\"-n\", \"-i\"]\ncolor = \"always\"\nignore = [\"target\"]\n[types]\nweb = [\"*.html\"]\n",
+            )
+            .unwrap();
+        let config = Config::with_profile(
+            args(&["-s", "--color=never", "-t", "web", "fn"]),
+            profile.clone(),
+        )
+        .unwrap();
+        assert!(config.line_number);
+        assert!(config.case_sensitive);
+        assert_eq!(config.color, ColorChoice::Never);
+        assert_eq!(config.filter.exclude[0].as_str(), "target");
+        assert_eq!(config.filter.include[0].as_str(), "*.html");
+
+        match Config::with_profile(args(&["--debug-config"]), profile) {
+            Err(ConfigError::DebugConfig(text)) => {
+                assert!(text.contains(".minigreprc"));
+                assert!(text.contains("color: Always"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn profile_is_a_lower_layer() {
+        let profile = |flags: &str| {
+            let mut profile = Profile::builtin();
+            profile
+                .merge(std::path::Path::new(".minigreprc"), flags)
+                .map(|_| profile)
+        };
+
+        // 和命令行冲突的配置文件选项被忽略，而不是报错
+        let config = Config::with_profile(
+            args(&["--json", "fn"]),
+            profile("flags = [\"-c\"]").unwrap(),
+        )
+        .unwrap();
+        assert!(config.json && !config.count);
+        let config = Config::with_profile(
+            args(&["--fuzzy=1", "fn"]),
+            profile("flags = [\"-nC2\"]").unwrap(),
+        )
+        .unwrap();
+        assert!(config.line_number);
+        assert_eq!((config.before_context, config.after_context), (0, 0));
+        // 同一层里的冲突仍然是错误
+        assert!(matches!(
+            Config::with_profile(args(&["--json", "-c", "fn"]), Profile::builtin()),
+            Err(ConfigError::Conflict(_, _))
+        ));
+
+        // 缺少参数的选项在读配置文件时就报错，不会把命令行上的查询当作自己的参数
+        assert!(matches!(
+            profile("flags = [\"--include\"]"),
+            Err(ConfigError::ConfigFile(_, _))
+        ));
+        // 解决冲突之后 -f 的内容还在：文件只读一次，重试时不会再读（-f - 第二次读只能读到空内容）
+        let path = env::temp_dir().join(format!("minigrep-layered-{}", std::process::id()));
+        fs::write(&path, "alpha\nbeta\n").unwrap();
+        let mut pattern_files = BTreeMap::new();
+        let config = Config::with_profile(
+            args(&["--json", "-f", path.to_str().unwrap(), "src"]),
+            profile("flags = [\"-c\"]").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.patterns, vec!["alpha", "beta"]);
+        assert!(config.json && !config.count);
+        let options = [(String::from("file"), path.to_str().map(String::from))];
+        Config::build(
+            options.iter(),
+            &[],
+            &Profile::builtin(),
+            &[],
+            &mut pattern_files,
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        let config = Config::build(
+            options.iter(),
+            &[],
+            &Profile::builtin(),
+            &[],
+            &mut pattern_files,
+        )
+        .unwrap();
+        assert_eq!(config.patterns, vec!["alpha", "beta"]);
+
+        let mut dangling = Profile::builtin();
+        dangling.args.push(String::from("--include"));
+        assert!(matches!(
+            Config::with_profile(args(&["fn", "src"]), dangling),
+            Err(ConfigError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn watch_options() {
+        let config =
+            Config::new(args(&["--watch", "--watch-interval=250", "ERROR", "log"])).unwrap();
+        assert!(config.watch);
+        assert_eq!(config.watch_interval, Duration::from_millis(250));
+        assert!(matches!(
+            Config::new(args(&["--watch", "-c", "ERROR", "log"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+        assert!(matches!(
+            Config::new(args(&["--watch-interval", "0", "ERROR", "log"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+    }
+
+    #[test]
+    fn json_conflicts() {
+        assert!(Config::new(args(&["--json", "the", "src"])).unwrap().json);
+        assert!(matches!(
+            Config::new(args(&["--json", "-c", "the", "src"])),
+            Err(ConfigError::Conflict(_, _))
+        ));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let path = env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+        fs::write(&path, "evil.com\r\n10.0.0.1\n").unwrap();
+        let config = Config::new(args(&[
+            "-e",
+            "first",
+            "-f",
+            path.to_str().unwrap(),
+            "--regexp=last",
+            "src",
+        ]))
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            config.patterns,
+            vec!["first", "evil.com", "10.0.0.1", "last"]
+        );
+        assert_eq!(config.paths, vec!["src"]);
+        assert!(matches!(config.matcher, Pattern::Multi(_)));
+
+        assert!(matches!(
+            Config::new(args(&["-f", "/nonexistent/patterns"])),
+            Err(ConfigError::PatternFile(_, _))
+        ));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = Config::new(args(&["-n", "--", "-v", "--help"])).unwrap();
+        assert_eq!(config.query, "-v");
+        assert_eq!(config.paths, vec!["--help"]);
+        assert!(!config.invert);
+    }
+
+    #[test]
+    fn unknown_and_informational_options() {
+        assert!(matches!(
+            Config::new(args(&["-x", "the", "poem.txt"])),
+            Err(ConfigError::UnknownOption(_))
+        ));
+        assert!(matches!(
+            Config::new(args(&["--count=3", "the", "poem.txt"])),
+            Err(ConfigError::UnexpectedValue(_))
+        ));
+        assert!(matches!(
+            Config::new(args(&["--help"])),
+            Err(ConfigError::Help)
+        ));
+        assert!(matches!(
+            Config::new(args(&["-V"])),
+            Err(ConfigError::Version)
+        ));
+    }
+}
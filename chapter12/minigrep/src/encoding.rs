@@ -0,0 +1,171 @@
+// 文本编码：默认直接搜索原始字节，不是合法 UTF-8 的部分显示为 U+FFFD；
+// 用 --encoding 指定编码时先解码成 UTF-8 再匹配，这时 -b 的字节偏移是解码后的偏移
+use encoding_rs::{Decoder, GBK, UTF_16BE, UTF_16LE, UTF_8};
+use std::io::{self, BufRead, BufReader, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    // 只根据 BOM 识别 UTF-8 和 UTF-16，没有 BOM 时按原始字节搜索
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Gbk,
+}
+
+impl Encoding {
+    // 不区分大小写，接受几种常见的写法
+    pub fn from_label(label: &str) -> Option<Encoding> {
+        match label.to_ascii_lowercase().as_str() {
+            "auto" => Some(Encoding::Auto),
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "gbk" | "gb2312" | "cp936" => Some(Encoding::Gbk),
+            _ => None,
+        }
+    }
+}
+
+// 按 encoding 把 reader 的内容解码成 UTF-8
+pub fn decode<'a>(
+    mut reader: Box<dyn BufRead + 'a>,
+    encoding: Encoding,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    // encoding_rs 的解码器会识别 BOM，BOM 和指定的编码不一致时以 BOM 为准
+    let decoder = match encoding {
+        Encoding::Auto => {
+            let head = reader.fill_buf()?;
+            if head.starts_with(&[0xef, 0xbb, 0xbf]) {
+                Some(UTF_8.new_decoder())
+            } else if head.starts_with(&[0xff, 0xfe]) {
+                Some(UTF_16LE.new_decoder())
+            } else if head.starts_with(&[0xfe, 0xff]) {
+                Some(UTF_16BE.new_decoder())
+            } else {
+                return Ok(reader);
+            }
+        }
+        Encoding::Utf8 => return Ok(reader),
+        Encoding::Utf16Le => Some(UTF_16LE.new_decoder()),
+        Encoding::Utf16Be => Some(UTF_16BE.new_decoder()),
+        // encoding_rs 按 WHATWG 标准把 Latin-1 当作 windows-1252，这里要的是真正的 ISO-8859-1
+        Encoding::Latin1 => None,
+        Encoding::Gbk => Some(GBK.new_decoder()),
+    };
+
+    Ok(Box::new(BufReader::new(DecodeReader {
+        inner: reader,
+        decoder,
+        decoded: Vec::new(),
+        pos: 0,
+        done: false,
+    })))
+}
+
+// 每次从底层读一块数据解码，解码结果暂存在 decoded 里交给调用方
+struct DecodeReader<R> {
+    inner: R,
+    // None 表示 Latin-1，每个字节就是同样码位的字符
+    decoder: Option<Decoder>,
+    decoded: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> DecodeReader<R> {
+    fn fill(&mut self) -> io::Result<()> {
+        let input = self.inner.fill_buf()?;
+        let last = input.is_empty();
+        let mut decoded = String::new();
+        let n = match &mut self.decoder {
+            None => {
+                decoded.extend(input.iter().map(|&b| b as char));
+                input.len()
+            }
+            Some(decoder) => {
+                // 预留足够的空间，一般一次就能把这一块输入解码完，解码不完的部分留到下一次
+                let capacity = decoder
+                    .max_utf8_buffer_length(input.len())
+                    .unwrap_or(input.len() * 3 + 16);
+                decoded.reserve(capacity);
+                let (_, read, _) = decoder.decode_to_string(input, &mut decoded, last);
+                read
+            }
+        };
+        self.inner.consume(n);
+        self.decoded = decoded.into_bytes();
+        self.pos = 0;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8], encoding: Encoding) -> String {
+        // 容量很小的 BufReader 保证多字节字符会被拆在两块里
+        let reader = Box::new(BufReader::with_capacity(3, bytes));
+        let mut text = String::new();
+        decode(reader, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn decodes_legacy_encodings() {
+        // “中文” 的 GBK 编码
+        assert_eq!(
+            decoded(&[0xd6, 0xd0, 0xce, 0xc4, b'\n'], Encoding::Gbk),
+            "中文\n"
+        );
+        assert_eq!(decoded(b"caf\xe9 \x80", Encoding::Latin1), "café \u{80}");
+        assert_eq!(
+            decoded(&[b'h', 0, b'i', 0, b'\n', 0], Encoding::Utf16Le),
+            "hi\n"
+        );
+        assert_eq!(decoded(&[0, b'h', 0, b'i'], Encoding::Utf16Be), "hi");
+    }
+
+    #[test]
+    fn sniffs_bom() {
+        assert_eq!(
+            decoded(&[0xff, 0xfe, b'o', 0, b'k', 0], Encoding::Auto),
+            "ok"
+        );
+        assert_eq!(
+            decoded(&[0xfe, 0xff, 0, b'o', 0, b'k'], Encoding::Auto),
+            "ok"
+        );
+        // BOM 优先于指定的编码
+        assert_eq!(decoded(&[0xfe, 0xff, 0, b'o'], Encoding::Utf16Le), "o");
+        // 没有 BOM 时原样返回
+        let mut raw = Vec::new();
+        decode(Box::new(&b"a\xffb"[..]), Encoding::Auto)
+            .unwrap()
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw, b"a\xffb");
+    }
+}
@@ -0,0 +1,198 @@
+// --field：只在记录的某个字段里匹配，支持 CSV（按表头中的列名或从 1 开始的列号）
+// 和 JSON Lines（按 user.email 这样的路径，数组用数字下标）
+// 每一行是一条记录，输出的仍然是整条记录；字段值在行内的位置能确定时，高亮的是字段里的匹配
+use crate::{Matcher, Span};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    // None 表示表头里没有这一列，任何记录都不匹配
+    Csv(Option<usize>),
+    Json(Vec<String>),
+}
+
+impl Field {
+    // 按 CSV 表头找出 name 所在的列，找不到时把 name 当作列号
+    pub fn csv(header: &str, name: &str) -> Field {
+        let column = split_csv(header)
+            .iter()
+            .position(|field| field.value == name)
+            .or_else(|| name.parse::<usize>().ok().filter(|&n| n > 0).map(|n| n - 1));
+        Field::Csv(column)
+    }
+
+    pub fn json(path: &str) -> Field {
+        Field::Json(path.split('.').map(String::from).collect())
+    }
+
+    // 字段的值，以及值在行内开始的字节下标（值和原文不完全一样时没有）
+    pub fn value(&self, line: &str) -> Option<(String, Option<usize>)> {
+        match self {
+            Field::Csv(column) => {
+                let field = split_csv(line).into_iter().nth((*column)?)?;
+                let start = if field.quoted {
+                    None
+                } else {
+                    Some(field.start)
+                };
+                Some((field.value, start))
+            }
+            Field::Json(path) => {
+                let record: Value = serde_json::from_str(line).ok()?;
+                let value = path.iter().try_fold(&record, |value, key| match value {
+                    Value::Object(map) => map.get(key),
+                    Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+                    _ => None,
+                })?;
+                Some(match value {
+                    Value::String(s) => {
+                        // 没有转义字符时字符串在行内原样出现，只出现一次才能确定位置
+                        let raw = serde_json::to_string(s).ok()?;
+                        let start = match line.match_indices(&raw).count() {
+                            1 if raw.len() == s.len() + 2 => line.find(&raw).map(|i| i + 1),
+                            _ => None,
+                        };
+                        (s.clone(), start)
+                    }
+                    Value::Null => (String::new(), None),
+                    other => (other.to_string(), None),
+                })
+            }
+        }
+    }
+}
+
+// 把匹配器限制在一个字段里
+pub struct FieldMatcher<'a, M: ?Sized> {
+    field: Field,
+    inner: &'a M,
+}
+
+impl<'a, M: Matcher + ?Sized> FieldMatcher<'a, M> {
+    pub fn new(field: Field, inner: &'a M) -> FieldMatcher<'a, M> {
+        FieldMatcher { field, inner }
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for FieldMatcher<'_, M> {
+    fn is_match(&self, line: &str) -> bool {
+        self.field
+            .value(line)
+            .is_some_and(|(value, _)| self.inner.is_match(&value))
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        match self.field.value(line) {
+            Some((value, Some(start))) => self
+                .inner
+                .find_iter(&value)
+                .into_iter()
+                .map(|span| Span {
+                    start: start + span.start,
+                    end: start + span.end,
+                    pattern: span.pattern,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+struct CsvField {
+    value: String,
+    // 字段在行内开始的字节下标
+    start: usize,
+    quoted: bool,
+}
+
+// 按 RFC 4180 拆分一行 CSV：逗号分隔，双引号包住的字段里可以有逗号，"" 表示一个双引号
+// 记录按行处理，引号里的换行不支持
+fn split_csv(line: &str) -> Vec<CsvField> {
+    let mut fields = Vec::new();
+    let mut field = CsvField {
+        value: String::new(),
+        start: 0,
+        quoted: false,
+    };
+    let mut in_quotes = false;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek().map(|&(_, c)| c) == Some('"') {
+                    field.value.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.value.is_empty() && !field.quoted => {
+                in_quotes = true;
+                field.quoted = true;
+            }
+            ',' if !in_quotes => {
+                fields.push(field);
+                field = CsvField {
+                    value: String::new(),
+                    start: i + 1,
+                    quoted: false,
+                };
+            }
+            c => field.value.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal::Literal;
+
+    #[test]
+    fn csv_columns() {
+        let header = "id,name,email";
+        let field = Field::csv(header, "email");
+        assert_eq!(field, Field::Csv(Some(2)));
+        assert_eq!(Field::csv(header, "2"), Field::Csv(Some(1)));
+        assert_eq!(Field::csv(header, "phone"), Field::Csv(None));
+
+        let line = r#"7,"Doe, ""JD"" John",jd@example.com"#;
+        assert_eq!(
+            Field::csv(header, "name").value(line),
+            Some((r#"Doe, "JD" John"#.to_string(), None))
+        );
+        assert_eq!(
+            field.value(line),
+            Some(("jd@example.com".to_string(), Some(21)))
+        );
+    }
+
+    #[test]
+    fn json_paths() {
+        let line = r#"{"user":{"email":"a@example.com","tags":["admin"]},"id":3}"#;
+        assert_eq!(
+            Field::json("user.email").value(line),
+            Some(("a@example.com".to_string(), Some(18)))
+        );
+        assert_eq!(
+            Field::json("user.tags.0").value(line),
+            Some(("admin".to_string(), Some(42)))
+        );
+        assert_eq!(Field::json("id").value(line), Some(("3".to_string(), None)));
+        assert_eq!(Field::json("user.phone").value(line), None);
+        assert_eq!(Field::json("id").value("not json"), None);
+    }
+
+    #[test]
+    fn matches_only_inside_field() {
+        let literal = Literal::new("example", true, false);
+        let matcher = FieldMatcher::new(Field::json("user.email"), &literal);
+        let line = r#"{"user":{"email":"a@example.com"},"note":"example"}"#;
+        assert!(matcher.is_match(line));
+        assert_eq!(matcher.find_iter(line), vec![Span::new(20, 27)]);
+        assert!(!matcher.is_match(r#"{"user":{"email":"a@test"},"note":"example"}"#));
+    }
+}
@@ -0,0 +1,144 @@
+// --fuzzy=K 近似匹配：行中只要有一段和查询的编辑距离（Levenshtein 距离）不超过 K 就算匹配
+// 用的是 Sellers 算法，即允许匹配从行内任意位置开始的编辑距离动态规划，按字符而不是字节计算
+use crate::unicode::fold_simple;
+use crate::{Matcher, Span};
+
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    query: Vec<char>,
+    max_distance: usize,
+    case_sensitive: bool,
+}
+
+// 动态规划表中的一格：到这里为止的最小编辑次数，以及对应匹配在行内开始的字符下标
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    cost: usize,
+    start: usize,
+}
+
+impl Cell {
+    // 编辑次数相同时选开始得更早、也就是更长的匹配，例如 kitten 对 sittin 时取整个 sittin 而不是 ittin
+    fn better(self, other: Cell) -> Cell {
+        if (other.cost, other.start) < (self.cost, self.start) {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl Fuzzy {
+    // 不区分大小写时按简单大小写折叠逐个字符比较
+    pub fn new(query: &str, max_distance: usize, case_sensitive: bool) -> Fuzzy {
+        Fuzzy {
+            query: query
+                .chars()
+                .map(|c| if case_sensitive { c } else { fold_simple(c) })
+                .collect(),
+            max_distance,
+            case_sensitive,
+        }
+    }
+
+    // 行中和查询最接近的一段：返回编辑距离和它的范围，距离相同时取最靠前的
+    pub fn best(&self, line: &str) -> (usize, Span) {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let byte_at = |i: usize| chars.get(i).map_or(line.len(), |&(b, _)| b);
+        let m = self.query.len();
+
+        // prev[i] 是查询前 i 个字符匹配到上一个字符为止的结果；行首之前只能靠插入
+        let mut prev: Vec<Cell> = (0..=m).map(|i| Cell { cost: i, start: 0 }).collect();
+        let mut cur = prev.clone();
+        let mut best = (prev[m].cost, 0, 0);
+
+        for (j, &(_, c)) in chars.iter().enumerate() {
+            let c = if self.case_sensitive {
+                c
+            } else {
+                fold_simple(c)
+            };
+            // 查询的空前缀可以从任意位置开始，这正是和普通编辑距离的区别
+            cur[0] = Cell {
+                cost: 0,
+                start: j + 1,
+            };
+            for i in 1..=m {
+                let substitute = Cell {
+                    cost: prev[i - 1].cost + usize::from(self.query[i - 1] != c),
+                    start: prev[i - 1].start,
+                };
+                // 行里多出一个字符
+                let insert = Cell {
+                    cost: prev[i].cost + 1,
+                    start: prev[i].start,
+                };
+                // 行里少了查询中的一个字符
+                let delete = Cell {
+                    cost: cur[i - 1].cost + 1,
+                    start: cur[i - 1].start,
+                };
+                cur[i] = substitute.better(insert).better(delete);
+            }
+            if cur[m].cost < best.0 {
+                best = (cur[m].cost, cur[m].start, j + 1);
+            }
+            std::mem::swap(&mut prev, &mut cur);
+        }
+
+        let (distance, start, end) = best;
+        (distance, Span::new(byte_at(start), byte_at(end)))
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn is_match(&self, line: &str) -> bool {
+        self.best(line).0 <= self.max_distance
+    }
+
+    // 只给出最接近的那一段
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        match self.best(line) {
+            (distance, span) if distance <= self.max_distance && span.start < span.end => {
+                vec![span]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best<'a>(query: &str, line: &'a str) -> (usize, &'a str) {
+        let (distance, span) = Fuzzy::new(query, 2, false).best(line);
+        (distance, &line[span.start..span.end])
+    }
+
+    #[test]
+    fn finds_closest_substring() {
+        assert_eq!(best("recieve", "please receive it"), (2, "receive"));
+        assert_eq!(best("invoice", "INV0ICE #12"), (1, "INV0ICE"));
+        assert_eq!(best("colour", "the color red"), (1, "color"));
+        assert_eq!(best("hello", "say helo!"), (1, "helo"));
+        assert_eq!(best("abc", "abd abc"), (0, "abc"));
+    }
+
+    #[test]
+    fn ties_prefer_longer_match() {
+        // "xbc"（替换一个字符）和 "bc"（少一个字符）的距离都是 1，不论是否从行首开始都选更长的那个
+        assert_eq!(best("abc", "xbc"), (1, "xbc"));
+        assert_eq!(best("abc", " xbc"), (1, "xbc"));
+        assert_eq!(best("kitten", "sittin"), (2, "sittin"));
+    }
+
+    #[test]
+    fn limits_edit_distance() {
+        let fuzzy = Fuzzy::new("kitten", 2, true);
+        assert!(fuzzy.is_match("a sitten sat"));
+        assert!(!fuzzy.is_match("a kit"));
+        assert_eq!(fuzzy.find_iter("sittin"), vec![Span::new(0, 6)]);
+        assert!(fuzzy.find_iter("dog").is_empty());
+    }
+}
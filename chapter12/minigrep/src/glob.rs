@@ -0,0 +1,279 @@
+// 一个够用的 glob 实现，供 --include/--exclude 和 .gitignore 规则使用
+// 支持的语法：
+// *      匹配除 / 以外的任意字符序列
+// ?      匹配除 / 以外的单个字符
+// [a-z]  字符类，[!a-z] 或 [^a-z] 表示取反
+// **     匹配任意层级的目录，a/**/b 可以匹配 a/b、a/x/b、a/x/y/b
+// \x     转义，匹配字面量 x
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    pattern: String,
+    chars: Vec<char>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GlobError {
+    pattern: String,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid glob pattern: {}", self.pattern)
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, GlobError> {
+        let chars: Vec<char> = pattern.chars().collect();
+
+        // 编译时只检查字符类是否闭合，真正的匹配在 is_match 中回溯完成
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => i += 2,
+                '[' => match class_end(&chars, i) {
+                    Some(end) => i = end + 1,
+                    None => {
+                        return Err(GlobError {
+                            pattern: pattern.to_string(),
+                        })
+                    }
+                },
+                _ => i += 1,
+            }
+        }
+
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            chars,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // 不含 / 的模式只和文件名比较，含 / 的模式和整个相对路径比较
+    pub fn has_separator(&self) -> bool {
+        self.chars.contains(&'/')
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        match_from(&self.chars, &text)
+    }
+}
+
+// 返回从 start 处的 [ 开始的字符类对应的 ] 的下标
+fn class_end(p: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if i < p.len() && (p[i] == '!' || p[i] == '^') {
+        i += 1;
+    }
+    // 紧跟在 [ 之后的 ] 是字面量
+    if i < p.len() && p[i] == ']' {
+        i += 1;
+    }
+    while i < p.len() {
+        if p[i] == ']' {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if class[i] <= c && c <= class[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    matched != negated
+}
+
+fn match_from(p: &[char], t: &[char]) -> bool {
+    if p.is_empty() {
+        return t.is_empty();
+    }
+
+    match p[0] {
+        '*' if p.get(1) == Some(&'*') => {
+            let rest = &p[2..];
+            // "**/" 可以匹配零层目录
+            if rest.first() == Some(&'/') && match_from(&rest[1..], t) {
+                return true;
+            }
+            (0..=t.len()).any(|i| match_from(rest, &t[i..]))
+        }
+        '*' => {
+            for i in 0..=t.len() {
+                if i > 0 && t[i - 1] == '/' {
+                    break;
+                }
+                if match_from(&p[1..], &t[i..]) {
+                    return true;
+                }
+            }
+            false
+        }
+        '?' => !t.is_empty() && t[0] != '/' && match_from(&p[1..], &t[1..]),
+        '[' => {
+            // Glob::new 已经保证了字符类是闭合的
+            let end = class_end(p, 0).unwrap();
+            !t.is_empty()
+                && t[0] != '/'
+                && class_matches(&p[1..end], t[0])
+                && match_from(&p[end + 1..], &t[1..])
+        }
+        '\\' if p.len() > 1 => !t.is_empty() && t[0] == p[1] && match_from(&p[2..], &t[1..]),
+        c => !t.is_empty() && t[0] == c && match_from(&p[1..], &t[1..]),
+    }
+}
+
+// .gitignore 中的一条规则
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    // ! 开头的规则会把之前被忽略的路径重新包含进来
+    negated: bool,
+    // 以 / 结尾的规则只匹配目录
+    dir_only: bool,
+    // 中间含有 / 的规则相对于 .gitignore 所在目录匹配，否则匹配任意层级下的文件名
+    anchored: bool,
+}
+
+// 一个 .gitignore 或 .ignore 文件，规则都相对于它所在的目录
+#[derive(Debug, Default)]
+pub struct IgnoreFile {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    pub fn from_file(path: &Path) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(path).ok()?;
+        Some(IgnoreFile::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> IgnoreFile {
+        let mut rules = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+
+            // 写错的规则直接跳过，和 git 的行为一致
+            if let Ok(glob) = Glob::new(line) {
+                rules.push(Rule {
+                    glob,
+                    negated,
+                    dir_only,
+                    anchored,
+                });
+            }
+        }
+
+        IgnoreFile { rules }
+    }
+
+    // 把另一个忽略文件的规则追加在后面，后追加的规则优先级更高
+    pub fn extend(&mut self, other: IgnoreFile) {
+        self.rules.extend(other.rules);
+    }
+
+    // relative 是相对于 .gitignore 所在目录、用 / 分隔的路径
+    // 返回 None 表示没有规则命中，由更上层目录的规则决定
+    pub fn matched(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+
+        // 后面的规则覆盖前面的规则，所以倒序找第一条命中的
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                if rule.dir_only && !is_dir {
+                    return false;
+                }
+                if rule.anchored {
+                    rule.glob.is_match(relative)
+                } else {
+                    rule.glob.is_match(name)
+                }
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let glob = Glob::new("*.rs").unwrap();
+        assert!(glob.is_match("lib.rs"));
+        assert!(!glob.is_match("src/lib.rs"));
+        assert!(Glob::new("ma?n.[a-z]s").unwrap().is_match("main.rs"));
+        assert!(Glob::new("[!a-m]*").unwrap().is_match("poem.txt"));
+        assert!(Glob::new("src/**/*.rs").unwrap().is_match("src/lib.rs"));
+        assert!(Glob::new("src/**/*.rs").unwrap().is_match("src/a/b/c.rs"));
+        assert!(Glob::new("**/target").unwrap().is_match("a/target"));
+        assert!(Glob::new("[").is_err());
+    }
+
+    #[test]
+    fn gitignore_rules() {
+        let ignore = IgnoreFile::parse(
+            "\
+# build output
+target/
+*.log
+!keep.log
+/output.txt
+docs/*.md
+",
+        );
+        assert_eq!(ignore.matched("target", true), Some(true));
+        assert_eq!(ignore.matched("target", false), None);
+        assert_eq!(ignore.matched("a/debug.log", false), Some(true));
+        assert_eq!(ignore.matched("keep.log", false), Some(false));
+        assert_eq!(ignore.matched("output.txt", false), Some(true));
+        assert_eq!(ignore.matched("src/output.txt", false), None);
+        assert_eq!(ignore.matched("docs/a.md", false), Some(true));
+        assert_eq!(ignore.matched("docs/a/b.md", false), None);
+    }
+}
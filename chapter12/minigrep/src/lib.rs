@@ -1,116 +1,491 @@
-use std::borrow::{Borrow, BorrowMut};
-use std::env;
+use archive::Input;
+use field::{Field, FieldMatcher};
+use fuzzy::Fuzzy;
+use literal::Literal;
+use memmap2::Mmap;
+use pattern::Pattern;
+use printer::Printer;
+use regex::Regex;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use walk::WalkError;
 
-#[derive(Debug)]
-pub struct Config {
-    pub query: String,
-    pub filename: String,
-    pub case_sensitive: bool,
+pub use config::{ColorChoice, Config, ConfigError};
+pub use profile::Profile;
+// 嵌入到其他程序时使用的库接口：Matcher 决定怎么匹配，Searcher 决定怎么搜，Sink 接收结果
+pub use matcher::Matcher;
+pub use searcher::{Discard, Searcher, Sink};
+
+mod archive;
+mod config;
+mod encoding;
+mod field;
+mod fuzzy;
+mod glob;
+mod literal;
+pub mod matcher;
+mod multi;
+mod parallel;
+mod pattern;
+mod printer;
+mod profile;
+mod replace;
+pub mod searcher;
+mod unicode;
+mod walk;
+mod watch;
+
+// 用 - 作为文件名时从标准输入读取
+const STDIN_PATH: &str = "-";
+// 不小于这个大小的文件才用内存映射，小文件映射的开销比直接读取更大
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+// 一次匹配在行内的字节范围 [start, end)，pattern 是命中的模式按 -e/-f 顺序的下标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub pattern: usize,
 }
 
-impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
+impl Span {
+    // 只有一个模式时 pattern 总是 0
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+            pattern: 0,
+        }
+    }
+}
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
+// 一行文本以及它在文件中的位置，行号从 1 开始，字节偏移从 0 开始
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    pub number: usize,
+    pub offset: usize,
+    pub text: &'a str,
+}
 
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file name"),
+// Box<dyn Error> 意味着函数会返回实现了 Error trait 的类型，不过无需指定具体返回的值的类型
+// dyn 表示 动态的（dynamic）
+// 返回是否有被选中的行，main 据此决定退出码
+pub fn run(config: Config) -> Result<bool, Box<dyn Error>> {
+    // --watch 一直运行，直到被中断或者输出出错
+    if config.watch {
+        return match watch::watch(&config) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+            Err(e) => Err(e.into()),
+            Ok(()) => Ok(true),
         };
+    }
 
-        // read var from env
-        // 如果 CASE_INSENSITIVE 被设置为任何值，is_err 会返回 false 并将进行大小写不敏感搜索
-        // 这里我们只关心 CASE_INSENSITIVE 是否被设置了而不关心所设置的值，所以使用了 is_err 而不是 unwrap/expect
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+    let quiet = config.quiet;
+    let outcome = match search_all(config) {
+        Ok(outcome) => outcome,
+        // 下游提前关闭了管道（例如 minigrep ... | head），需要的内容已经输出，安静地结束
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
 
-        Ok(Config {
-            query,
-            filename,
-            case_sensitive,
-        })
+    // 和 grep 一样，-q 时只要找到了匹配就算成功，即使有文件出错
+    if outcome.failed > 0 && !(quiet && outcome.matched) {
+        return Err(format!("{} file(s) could not be searched", outcome.failed).into());
     }
+    Ok(outcome.matched)
 }
 
-// Box<dyn Error> 意味着函数会返回实现了 Error trait 的类型，不过无需指定具体返回的值的类型
-// dyn 表示 动态的（dynamic）
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
-    // println!("With text:\n{}", contents);
+// 全部文件搜索结束后的结果
+#[derive(Debug, Default)]
+struct Outcome {
+    matched: bool,
+    failed: usize,
+}
+
+impl Outcome {
+    // 单个文件出错不影响其他文件的搜索，报告之后继续；写输出出错则没有必要再搜下去
+    fn record(&mut self, result: Result<usize, SearchError>) -> io::Result<()> {
+        match result {
+            Ok(count) => self.matched |= count > 0,
+            Err(SearchError::File(message)) => {
+                eprintln!("minigrep: {}", message);
+                self.failed += 1;
+            }
+            Err(SearchError::Output(e)) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+enum SearchError {
+    // 带有文件路径的错误信息
+    File(String),
+    Output(io::Error),
+}
+
+// 和 grep 一样，只要可能搜索多个文件就在每行前面加上文件路径
+fn with_filename(config: &Config) -> bool {
+    config.paths.len() > 1
+        || config
+            .paths
+            .iter()
+            .any(|p| fs::metadata(p).map(|m| m.is_dir()).unwrap_or(false))
+}
+
+fn search_all(config: Config) -> io::Result<Outcome> {
+    let with_filename = with_filename(&config);
+
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), &config, with_filename);
+    let files = walk::walk(&config.paths, &config.filter);
+
+    // -q 找到第一个匹配就可以结束，并行反而要等其他文件，所以总是串行搜索
+    let outcome = if config.threads > 1 && files.len() > 1 && !config.quiet {
+        parallel::search_parallel(config, files, with_filename, &mut printer)?
+    } else {
+        let mut outcome = Outcome::default();
+        for file in files {
+            outcome.record(search_target(&config, file, &mut printer))?;
+            if config.quiet && outcome.matched {
+                break;
+            }
+        }
+        outcome
+    };
+
+    printer.finish()?;
+    Ok(outcome)
+}
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+// 搜索 walk 得到的一项，返回选中的行数
+fn search_target<W: Write>(
+    config: &Config,
+    file: Result<PathBuf, WalkError>,
+    printer: &mut Printer<W>,
+) -> Result<usize, SearchError> {
+    let path = file.map_err(|e| SearchError::File(e.to_string()))?;
+    search_file(config, &path, printer).map_err(|e| search_error(&path, e))
+}
+
+fn search_error(path: &Path, e: Box<dyn Error>) -> SearchError {
+    match e.downcast::<io::Error>() {
+        // 读文件不会出现 BrokenPipe，它只可能来自写输出
+        Ok(e) if e.kind() == io::ErrorKind::BrokenPipe => SearchError::Output(*e),
+        Ok(e) => SearchError::File(format!("{}: {}", path.display(), e)),
+        Err(e) => SearchError::File(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn search_file<W: Write>(
+    config: &Config,
+    path: &Path,
+    printer: &mut Printer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let stdin = path == Path::new(STDIN_PATH);
+    let shown = if stdin {
+        Path::new("(standard input)")
+    } else {
+        path
+    };
+
+    // 替换需要整个文件的内容才能改写，所以不走流式搜索
+    if config.replace.is_some() {
+        let bytes = if stdin {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            bytes
+        } else {
+            fs::read(path)?
+        };
+        if archive::Compression::detect(&bytes) != archive::Compression::None {
+            return Err("can't replace inside a compressed file".into());
+        }
+        if is_binary(&bytes) {
+            return Ok(0);
+        }
+        // 改写的结果按 UTF-8 写回，其他编码的文件和二进制文件一样跳过，不算出错
+        let contents = match String::from_utf8(bytes) {
+            Ok(contents) => contents,
+            Err(_) => {
+                eprintln!(
+                    "minigrep: {}: not UTF-8 text, skipped by --replace",
+                    shown.display()
+                );
+                return Ok(0);
+            }
+        };
+        return replace::replace(config, shown, !stdin, &contents, printer);
+    }
+
+    let map;
+    let input = if stdin {
+        archive::open(io::stdin().lock())?
     } else {
-        search_case_insensitive(&config.query, &contents)
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        // 大的普通文件映射到内存里，整个文件就是 reader 的缓冲区，省去一次次 read 和复制
+        // 管道、设备之类的不能映射
+        if config.mmap && metadata.is_file() && metadata.len() >= MMAP_THRESHOLD {
+            // 搜索过程中文件被别的进程截断会收到 SIGBUS，和 grep、ripgrep 的取舍一样，
+            // 需要避免时可以用 --no-mmap
+            map = unsafe { Mmap::map(&file)? };
+            archive::open(&map[..])?
+        } else {
+            archive::open(BufReader::new(file))?
+        }
+    };
+    match input {
+        Input::Text(reader) => {
+            let reader = encoding::decode(reader, config.encoding)?;
+            search_stream(config, shown, reader, (0, 0), printer)
+        }
+        Input::Tar(archive) => search_archive(config, shown, archive, printer),
+    }
+}
+
+// tar 包中的每个普通文件分别搜索，结果显示为 archive.tar:inner/path，
+// 即使只搜索这一个文件也要显示路径，否则分不清是哪个文件里的内容
+fn search_archive<R: Read, W: Write>(
+    config: &Config,
+    path: &Path,
+    mut archive: tar::Archive<R>,
+    printer: &mut Printer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let with_filename = printer.set_with_filename(true);
+    let result = (|| -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        for entry in archive.entries()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let inner = format!("{}:{}", path.display(), entry.path()?.display());
+            // 包里的文件本身也可能是压缩过的
+            let reader = archive::decompress(BufReader::new(entry))?;
+            let reader = encoding::decode(reader, config.encoding)?;
+            count += search_stream(config, Path::new(&inner), reader, (0, 0), printer)?;
+            if config.quiet && count > 0 {
+                break;
+            }
+        }
+        Ok(count)
+    })();
+    printer.set_with_filename(with_filename);
+    result
+}
+
+// start 是 reader 之前已经读过的行数和字节数，--watch 只搜索文件新追加的部分时不是 0
+fn search_stream<R: BufRead, W: Write>(
+    config: &Config,
+    path: &Path,
+    mut reader: R,
+    start: (usize, usize),
+    printer: &mut Printer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    // 和 git 一样，开头一段里出现 NUL 字节就当作二进制文件跳过
+    // fill_buf 只是查看缓冲区里的内容，不会消耗它
+    if is_binary(reader.fill_buf()?) {
+        return Ok(0);
+    }
+
+    let mut searcher = config.searcher().skipped(start.0, start.1);
+
+    // --field 时以 { 开头的输入按 JSON Lines 处理，否则按 CSV 处理，第一行是表头
+    let field_matcher;
+    let matcher: &dyn Matcher = match &config.field {
+        Some(name) => {
+            let head = reader.fill_buf()?;
+            let field = if head.trim_ascii_start().starts_with(b"{") {
+                Field::json(name)
+            } else if start.0 > 0 {
+                // 从文件中间开始时表头要回到文件开头去读
+                let mut header = String::new();
+                BufReader::new(File::open(path)?).read_line(&mut header)?;
+                Field::csv(header.trim_end_matches(['\n', '\r']), name)
+            } else {
+                let mut header = Vec::new();
+                let n = reader.read_until(b'\n', &mut header)?;
+                searcher = searcher.skipped(1, n);
+                let header = String::from_utf8_lossy(&header);
+                Field::csv(header.trim_end_matches(['\n', '\r']), name)
+            };
+            field_matcher = FieldMatcher::new(field, &config.matcher);
+            &field_matcher
+        }
+        None => &config.matcher,
     };
 
-    for line in results {
-        println!("{}", line);
+    // -q/-l/-L 只关心有没有匹配，找到一行就可以停止读取
+    if config.quiet || config.files_with_matches || config.files_without_match {
+        let count = searcher
+            .max_count(Some(1))
+            .search_reader(matcher, reader, Discard)?;
+        if config.quiet {
+            return Ok(count);
+        }
+        if (count > 0) == config.files_with_matches {
+            printer.print_path(path)?;
+        }
+        return Ok(count);
+    }
+    if config.count {
+        let count = searcher.search_reader(matcher, reader, Discard)?;
+        printer.print_count(path, count)?;
+        return Ok(count);
+    }
+
+    // --fuzzy 时同一个文件中的匹配行按编辑距离排序，距离相同的保持原来的顺序
+    if let Pattern::Fuzzy(fuzzy) = &config.matcher {
+        let mut ranked = Ranked {
+            fuzzy,
+            lines: Vec::new(),
+        };
+        let count = searcher.search_reader(fuzzy, reader, &mut ranked)?;
+        ranked.lines.sort_by_key(|ranked| ranked.0);
+
+        printer.begin_file(path);
+        for (_, number, offset, text, span) in &ranked.lines {
+            let line = Line {
+                number: *number,
+                offset: *offset,
+                text,
+            };
+            printer.matched(&line, &[*span])?;
+        }
+        printer.end_file()?;
+        return Ok(count);
+    }
+
+    printer.begin_file(path);
+    let count = searcher.search_reader(matcher, reader, &mut *printer)?;
+    printer.end_file()?;
+    Ok(count)
+}
+
+// 收集匹配行的 Sink，行会重新从 contents 中切出来，这样结果借用的是 contents 而不是搜索时的缓冲区
+struct Collect<'a> {
+    contents: &'a str,
+    spans: bool,
+    lines: Vec<(Line<'a>, Vec<Span>)>,
+}
+
+impl Sink for Collect<'_> {
+    fn matched(&mut self, line: &Line, spans: &[Span]) -> io::Result<bool> {
+        let end = line.offset + line.text.len();
+        let line = Line {
+            number: line.number,
+            offset: line.offset,
+            text: &self.contents[line.offset..end],
+        };
+        self.lines.push((line, spans.to_vec()));
+        Ok(true)
+    }
+
+    fn needs_spans(&self) -> bool {
+        self.spans
+    }
+}
+
+// 收集 --fuzzy 匹配的行以及编辑距离和最接近的一段，等整个文件搜索完再排序输出
+struct Ranked<'a> {
+    fuzzy: &'a Fuzzy,
+    lines: Vec<(usize, usize, usize, String, Span)>,
+}
+
+impl Sink for Ranked<'_> {
+    fn matched(&mut self, line: &Line, _spans: &[Span]) -> io::Result<bool> {
+        let (distance, span) = self.fuzzy.best(line.text);
+        self.lines.push((
+            distance,
+            line.number,
+            line.offset,
+            line.text.to_string(),
+            span,
+        ));
+        Ok(true)
+    }
+
+    // 距离和范围在 matched 里一起算出来
+    fn needs_spans(&self) -> bool {
+        false
     }
+}
+
+// 在内存中的字符串里搜索，spans 为 true 时同时给出每一行中所有匹配的范围
+fn search_str<'a, M>(
+    searcher: &Searcher,
+    matcher: &M,
+    contents: &'a str,
+    spans: bool,
+) -> Vec<(Line<'a>, Vec<Span>)>
+where
+    M: Matcher + ?Sized,
+{
+    let mut collect = Collect {
+        contents,
+        spans,
+        lines: Vec::new(),
+    };
+    // 从 &[u8] 读取不会出现 IO 错误，内容也一定是合法的 UTF-8
+    searcher
+        .search_str(matcher, contents, &mut collect)
+        .unwrap();
+    collect.lines
+}
+
+// 和 search 系列函数一样按 config 查找，但结果里带有行号和字节偏移
+pub fn search_lines<'a>(config: &Config, contents: &'a str) -> Vec<Line<'a>> {
+    search_str(&config.searcher(), &config.matcher, contents, false)
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect()
+}
 
-    Ok(())
+// 和 search_lines 一样，但同时给出每一行中所有匹配的范围，可以用来高亮匹配的部分
+// -v 时选中的行里没有匹配，范围总是空的
+pub fn search_spans<'a>(config: &Config, contents: &'a str) -> Vec<(Line<'a>, Vec<Span>)> {
+    search_str(&config.searcher(), &config.matcher, contents, true)
 }
 
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
+// 只要匹配的行的文本
+fn matched_text<'a, M: Matcher>(matcher: &M, contents: &'a str) -> Vec<&'a str> {
+    search_str(&Searcher::new(), matcher, contents, false)
+        .into_iter()
+        .map(|(line, _)| line.text)
         .collect()
 }
 
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+// 和 git 一样只检查开头的这么多字节
+const BINARY_PREFIX: usize = 8000;
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_PREFIX).any(|&b| b == 0)
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    matched_text(&Literal::new(query, true, false), contents)
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     // 关于迭代器的性能：迭代器作为一个高级的抽象，被编译成了与手写的底层代码大体一致性能的代码
     // 迭代器是 Rust 的 零成本抽象（zero-cost abstractions） 之一，它意味着抽象并不会引入运行时开销
-    contents
-        .lines()
-        .filter(|line| {
-            line.to_lowercase()
-                .as_str()
-                .contains(query.to_lowercase().as_str())
-        })
-        .collect()
+    // 查询只折叠一次；按 Unicode 大小写折叠比较，而不是对每一行调用 to_lowercase
+    matched_text(&Literal::new(query, false, false), contents)
+}
+
+// 正则模式：字符类、锚点、分支、重复和捕获组都交给 regex crate 处理
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<&'a str> {
+    matched_text(re, contents)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{search, search_case_insensitive, Config};
-
-    //    #[test]
-    //    #[ignore]
-    //    fn not_enough_args() {
-    //        let args = vec![String::from("/path/to/script")];
-    //        let config = Config::new(&args);
-    //        match config {
-    //            Ok(c) => panic!("config should not be Ok"),
-    //            Err(e) => {
-    //                println!("err: {}", e);
-    //                assert_eq!(e, "not enough arguments")
-    //            }
-    //        }
-    //    }
-    //
-    //    #[test]
-    //    #[ignore]
-    //    fn enough_args() {
-    //        let args = vec![
-    //            String::from("/path/to/script"),
-    //            String::from("the"),
-    //            String::from("poem.txt"),
-    //        ];
-    //        let config = Config::new(&args);
-    //        match config {
-    //            Ok(c) => (),
-    //            Err(e) => {
-    //                println!("err: {}", e);
-    //                panic!("config should be Ok");
-    //            }
-    //        }
-    //    }
+    use crate::{
+        search, search_case_insensitive, search_lines, search_regex, search_spans, Config, Line,
+        Span,
+    };
 
     #[test]
     fn case_sensitive() {
@@ -137,4 +512,81 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn regex_match() {
+        let re = regex::Regex::new(r"^(Rust|Trust)\b|t[a-z]pe\.$").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.
+Duct tape.";
+        assert_eq!(
+            vec!["Rust:", "Trust me.", "Duct tape."],
+            search_regex(&re, contents)
+        );
+    }
+
+    #[test]
+    fn lines_carry_positions() {
+        let args = vec!["minigrep", "-i", "rust", "poem.txt"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        let contents = "Rust:\r\nsafe, fast, productive.\nTrust me.";
+        assert_eq!(
+            vec![
+                Line {
+                    number: 1,
+                    offset: 0,
+                    text: "Rust:"
+                },
+                Line {
+                    number: 3,
+                    offset: 31,
+                    text: "Trust me."
+                }
+            ],
+            search_lines(&config, contents)
+        );
+    }
+
+    #[test]
+    fn spans_of_matches() {
+        let args = vec!["minigrep", "-i", "rust", "poem.txt"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        let results = search_spans(&config, "Rust or rust?\nno\nTrust me.");
+        let spans: Vec<_> = results.iter().map(|(_, spans)| spans.clone()).collect();
+        assert_eq!(
+            spans,
+            vec![
+                vec![Span::new(0, 4), Span::new(8, 12)],
+                vec![Span::new(1, 5)]
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_skips_files_that_are_not_utf8() {
+        use crate::printer::Printer;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("minigrep-latin1-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("latin1.txt"), b"caf\xe9 rust\n").unwrap();
+        fs::write(dir.join("utf8.txt"), "café rust\n").unwrap();
+
+        let args = vec!["minigrep", "--replace", "go", "rust"];
+        let config = Config::new(args.into_iter().map(String::from)).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        for name in ["latin1.txt", "utf8.txt"] {
+            let file = Ok(dir.join(name));
+            assert!(crate::search_target(&config, file, &mut printer).is_ok());
+        }
+        printer.finish().unwrap();
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert!(!output.contains("latin1.txt"));
+        assert!(output.contains("+café go\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,120 @@
+// 普通（非正则）查询的匹配：区分或不区分大小写，以及 -w 整词匹配
+use crate::unicode::{word_boundaries, FoldedQuery};
+use crate::{Matcher, Span};
+
+#[derive(Debug, Clone)]
+pub struct Literal {
+    query: String,
+    // 不区分大小写时使用折叠后的查询，None 表示区分大小写
+    folded: Option<FoldedQuery>,
+    // 整词匹配：匹配的两端都必须是 Unicode 单词边界
+    word: bool,
+}
+
+impl Literal {
+    pub fn new(query: &str, case_sensitive: bool, word: bool) -> Literal {
+        Literal {
+            query: query.to_string(),
+            folded: if case_sensitive {
+                None
+            } else {
+                Some(FoldedQuery::new(query))
+            },
+            word,
+        }
+    }
+
+    // 区分大小写时所有可能重叠的出现位置
+    fn occurrences(&self, line: &str) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut from = 0;
+        while let Some(i) = line[from..].find(&self.query) {
+            let start = from + i;
+            spans.push(Span::new(start, start + self.query.len()));
+            from = start + line[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        spans
+    }
+}
+
+impl Matcher for Literal {
+    fn is_match(&self, line: &str) -> bool {
+        // 和 str::contains 一样，空查询匹配任意行
+        if self.query.is_empty() {
+            return true;
+        }
+        if self.folded.is_none() && !self.word {
+            return line.contains(&self.query);
+        }
+        !self.find_iter(line).is_empty()
+    }
+
+    // 查询本身不含换行，大小写折叠和单词边界在换行处也不受影响
+    fn within_lines(&self) -> bool {
+        true
+    }
+
+    // 行内所有不重叠的匹配，从左到右
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        if self.query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut candidates = match &self.folded {
+            None if !self.word => {
+                return line
+                    .match_indices(&self.query)
+                    .map(|(start, m)| Span::new(start, start + m.len()))
+                    .collect()
+            }
+            None => self.occurrences(line),
+            Some(folded) => folded
+                .candidates(line)
+                .into_iter()
+                .map(|(start, end)| Span::new(start, end))
+                .collect(),
+        };
+
+        // 候选中可能有互相重叠的匹配，先按单词边界过滤再挑出不重叠的，
+        // 这样 aa 在 "aaa aa" 中被前面不是整词的匹配挡住的情况也不会漏掉
+        if self.word {
+            let bounds = word_boundaries(line);
+            candidates.retain(|span| {
+                bounds.binary_search(&span.start).is_ok() && bounds.binary_search(&span.end).is_ok()
+            });
+        }
+
+        let mut spans: Vec<Span> = Vec::with_capacity(candidates.len());
+        for span in candidates {
+            if spans.last().is_none_or(|last| span.start >= last.end) {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_words() {
+        let literal = Literal::new("aa", true, true);
+        assert_eq!(literal.find_iter("aaa aa"), vec![Span::new(4, 6)]);
+        assert!(!Literal::new("can", true, true).is_match("can't"));
+        assert!(Literal::new("中", true, true).is_match("中文"));
+        assert!(Literal::new("rust", false, true).is_match("I love RUST!"));
+        assert!(!Literal::new("rust", false, true).is_match("Trust me."));
+    }
+
+    #[test]
+    fn case_insensitive_is_folded() {
+        let literal = Literal::new("strasse", false, false);
+        assert!(literal.is_match("Die STRAßE"));
+        assert_eq!(
+            literal.find_iter("Straße, STRASSE"),
+            vec![Span::new(0, 7), Span::new(9, 16)]
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use minigrep::{run, Config};
+use minigrep::{run, Config, ConfigError};
+use std::io::{self, Write};
 use std::{env, process};
 
 fn main() {
@@ -6,18 +7,32 @@ fn main() {
     // 如果你需要接受包含无效 Unicode 字符的参数，使用 std::env::args_os 代替。
     // 这个函数返回 OsString 值而不是 String 值
     // collect 是一个经常需要注明类型的函数
-    let args: Vec<String> = env::args().collect();
+    // let args: Vec<String> = env::args().collect();
     // println!("{:?}", args);
 
     // unwrap_or_else 在 Err 时会调用一个 closure
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        // 将错误信息输出到 stderr
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+    let config = Config::load(env::args()).unwrap_or_else(|err| match err {
+        // --help、--version 和 --debug-config 的内容输出到 stdout 并正常退出
+        ConfigError::Help | ConfigError::Version | ConfigError::DebugConfig(_) => {
+            // 和搜索结果一样，输出管道被提前关闭（例如 | head）时安静地结束
+            let _ = writeln!(io::stdout(), "{}", err);
+            process::exit(0);
+        }
+        _ => {
+            // 将错误信息输出到 stderr
+            eprintln!("Problem parsing arguments: {}", err);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(2);
+        }
     });
 
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    // 退出码和 grep 一致：0 表示有选中的行，1 表示没有，2 表示出错
+    match run(config) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(2);
+        }
     }
 }
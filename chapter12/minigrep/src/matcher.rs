@@ -0,0 +1,105 @@
+// 匹配器：决定一行是否匹配，以及匹配在行内的位置
+// 自带的实现有普通字面量（Literal，区分或不区分大小写）、多个字面量（MultiLiteral）、
+// 正则表达式（regex::Regex）以及 CLI 按选项挑选其中之一的 Pattern
+use crate::Span;
+use regex::Regex;
+
+pub trait Matcher {
+    fn is_match(&self, line: &str) -> bool;
+
+    // 行内所有不重叠的匹配，从左到右
+    fn find_iter(&self, line: &str) -> Vec<Span>;
+
+    // 匹配不会跨行，多行文本中没有匹配就说明其中每一行都不匹配时返回 true，
+    // Searcher 会据此整块跳过没有匹配的内容；正则的 ^、$ 在多行文本中含义不同，所以默认是 false
+    fn within_lines(&self) -> bool {
+        false
+    }
+
+    // 把行内的每个匹配替换成 replacement
+    fn replace(&self, line: &str, replacement: &str) -> String {
+        let mut replaced = String::with_capacity(line.len());
+        let mut end = 0;
+        for span in self.find_iter(line) {
+            replaced.push_str(&line[end..span.start]);
+            replaced.push_str(replacement);
+            end = span.end;
+        }
+        replaced.push_str(&line[end..]);
+        replaced
+    }
+}
+
+impl Matcher for Regex {
+    fn is_match(&self, line: &str) -> bool {
+        Regex::is_match(self, line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        Regex::find_iter(self, line)
+            .map(|m| Span::new(m.start(), m.end()))
+            .collect()
+    }
+
+    // 可以用 $1、${name} 引用捕获组
+    fn replace(&self, line: &str, replacement: &str) -> String {
+        self.replace_all(line, replacement).into_owned()
+    }
+}
+
+// 引用和 Box 也可以直接当作匹配器使用
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        (**self).find_iter(line)
+    }
+
+    fn within_lines(&self) -> bool {
+        (**self).within_lines()
+    }
+
+    fn replace(&self, line: &str, replacement: &str) -> String {
+        (**self).replace(line, replacement)
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn is_match(&self, line: &str) -> bool {
+        (**self).is_match(line)
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        (**self).find_iter(line)
+    }
+
+    fn within_lines(&self) -> bool {
+        (**self).within_lines()
+    }
+
+    fn replace(&self, line: &str, replacement: &str) -> String {
+        (**self).replace(line, replacement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal::Literal;
+
+    #[test]
+    fn matchers_are_interchangeable() {
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(Literal::new("rust", true, false)),
+            Box::new(Literal::new("RUST", false, false)),
+            Box::new(Regex::new(r"r\w+t").unwrap()),
+        ];
+        for matcher in &matchers {
+            assert!(matcher.is_match("I trust rust"));
+            assert_eq!(matcher.find_iter("rust!"), vec![Span::new(0, 4)]);
+            assert_eq!(matcher.replace("rust!", "go"), "go!");
+        }
+    }
+}
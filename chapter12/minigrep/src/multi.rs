@@ -0,0 +1,183 @@
+// 多模式字面量查找：-e 重复多次或 -f 从文件读入成千上万个模式时，
+// 用 Aho-Corasick 自动机一次扫描就能找出所有模式的出现位置，而不是每个模式扫描一遍
+use crate::unicode::{fold_full, word_boundaries};
+use crate::{Matcher, Span};
+use aho_corasick::{AhoCorasick, BuildError};
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone)]
+pub struct MultiLiteral {
+    automaton: AhoCorasick,
+    // 自动机中的模式下标到原始模式下标的映射，空模式不放进自动机
+    ids: Vec<usize>,
+    // 有空模式时和 grep 一样匹配任意行
+    has_empty: bool,
+    case_sensitive: bool,
+    word: bool,
+}
+
+impl MultiLiteral {
+    pub fn new(
+        patterns: &[String],
+        case_sensitive: bool,
+        word: bool,
+    ) -> Result<MultiLiteral, BuildError> {
+        let mut ids = Vec::new();
+        let mut needles = Vec::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            if pattern.is_empty() {
+                continue;
+            }
+            ids.push(i);
+            // 不区分大小写时模式和被搜索的行都先做大小写折叠
+            needles.push(if case_sensitive {
+                pattern.clone()
+            } else {
+                pattern.chars().flat_map(fold_full).collect()
+            });
+        }
+
+        Ok(MultiLiteral {
+            automaton: AhoCorasick::new(&needles)?,
+            has_empty: ids.len() < patterns.len(),
+            ids,
+            case_sensitive,
+            word,
+        })
+    }
+}
+
+impl Matcher for MultiLiteral {
+    fn is_match(&self, line: &str) -> bool {
+        if self.has_empty {
+            return true;
+        }
+        if self.case_sensitive && !self.word {
+            return self.automaton.is_match(line);
+        }
+        !self.find_iter(line).is_empty()
+    }
+
+    fn within_lines(&self) -> bool {
+        true
+    }
+
+    // 行内所有不重叠的匹配，从左到右；同一位置有多个模式命中时取最长的
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        let folded;
+        let (haystack, map) = if self.case_sensitive {
+            (line, None)
+        } else if line.is_ascii() {
+            // ASCII 字符的折叠结果还是 ASCII，下标和原文一一对应
+            folded = line.to_ascii_lowercase();
+            (folded.as_str(), None)
+        } else {
+            let (text, map) = FoldMap::new(line);
+            folded = text;
+            (folded.as_str(), Some(map))
+        };
+
+        let mut candidates: Vec<Span> = self
+            .automaton
+            .find_overlapping_iter(haystack)
+            .filter_map(|m| {
+                let (start, end) = match &map {
+                    Some(map) => (map.starts[m.start()]?, map.ends[m.end()]?),
+                    None => (m.start(), m.end()),
+                };
+                Some(Span {
+                    start,
+                    end,
+                    pattern: self.ids[m.pattern().as_usize()],
+                })
+            })
+            .collect();
+
+        if self.word {
+            let bounds = word_boundaries(line);
+            candidates.retain(|span| {
+                bounds.binary_search(&span.start).is_ok() && bounds.binary_search(&span.end).is_ok()
+            });
+        }
+
+        candidates.sort_by_key(|span| (span.start, Reverse(span.end)));
+        let mut spans: Vec<Span> = Vec::new();
+        for span in candidates {
+            if spans.last().is_none_or(|last| span.start >= last.end) {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+}
+
+// 折叠后的文本中的字节下标到原文下标的映射
+// 只有原文字符折叠结果的开头/末尾才有对应的下标，落在中间的匹配会被丢弃
+struct FoldMap {
+    starts: Vec<Option<usize>>,
+    ends: Vec<Option<usize>>,
+}
+
+impl FoldMap {
+    fn new(line: &str) -> (String, FoldMap) {
+        let mut folded = String::with_capacity(line.len());
+        let mut starts = vec![None];
+        let mut ends = vec![Some(0)];
+        for (start, c) in line.char_indices() {
+            let first = folded.len();
+            for f in fold_full(c) {
+                folded.push(f);
+            }
+            starts.resize(folded.len() + 1, None);
+            ends.resize(folded.len() + 1, None);
+            starts[first] = Some(start);
+            ends[folded.len()] = Some(start + c.len_utf8());
+        }
+        (folded, FoldMap { starts, ends })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn found(multi: &MultiLiteral, line: &str) -> Vec<(usize, usize, usize)> {
+        multi
+            .find_iter(line)
+            .into_iter()
+            .map(|s| (s.start, s.end, s.pattern))
+            .collect()
+    }
+
+    #[test]
+    fn reports_which_pattern_hit() {
+        let multi =
+            MultiLiteral::new(&patterns(&["evil.com", "10.0.0.1", "evil"]), true, false).unwrap();
+        assert_eq!(
+            found(&multi, "GET evil.com from 10.0.0.1"),
+            vec![(4, 12, 0), (18, 26, 1)]
+        );
+        assert!(!multi.is_match("nothing here"));
+    }
+
+    #[test]
+    fn case_insensitive_and_words() {
+        let multi = MultiLiteral::new(&patterns(&["strasse", "ab"]), false, true).unwrap();
+        assert_eq!(found(&multi, "Die Straße"), vec![(4, 11, 0)]);
+        assert_eq!(found(&multi, "abc AB"), vec![(4, 6, 1)]);
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        let multi = MultiLiteral::new(&patterns(&["x", ""]), true, false).unwrap();
+        assert!(multi.is_match("anything"));
+        assert!(MultiLiteral::new(&[], true, false)
+            .unwrap()
+            .find_iter("anything")
+            .is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+// -j N 并行搜索：把文件分给第 20 章实现的线程池
+// 每个文件的结果先写进自己的缓冲区，再由主线程按文件顺序输出，
+// 所以输出顺序和串行搜索完全一样，不同文件的输出也不会交错在一起
+use crate::printer::Printer;
+use crate::walk::WalkError;
+use crate::{search_target, Config, Outcome, SearchError};
+use hello::ThreadPool;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+
+// 搜索一个文件并把结果写进缓冲区，测试中可以换成别的实现
+type Search =
+    fn(&Config, Result<PathBuf, WalkError>, &mut Printer<Vec<u8>>) -> Result<usize, SearchError>;
+
+pub fn search_parallel<W: Write>(
+    config: Config,
+    files: Vec<Result<PathBuf, WalkError>>,
+    with_filename: bool,
+    printer: &mut Printer<W>,
+) -> io::Result<Outcome> {
+    search_with(config, files, with_filename, printer, search_target)
+}
+
+fn search_with<W: Write>(
+    config: Config,
+    files: Vec<Result<PathBuf, WalkError>>,
+    with_filename: bool,
+    printer: &mut Printer<W>,
+    search: Search,
+) -> io::Result<Outcome> {
+    // 闭包要求 'static，所以 Config 通过 Arc 在线程之间共享
+    let config = Arc::new(config);
+    let pool = ThreadPool::silent(config.threads);
+    let (sender, receiver) = mpsc::channel();
+
+    for (index, file) in files.into_iter().enumerate() {
+        let config = Arc::clone(&config);
+        let sender = sender.clone();
+        pool.execute(move || {
+            let name = match &file {
+                Ok(path) => path.display().to_string(),
+                Err(e) => e.to_string(),
+            };
+            // 某个文件的搜索 panic 时也必须交回结果，否则排在它后面的文件都不会输出
+            // 已经写进缓冲区的部分输出不完整，直接丢掉，panic 当作这个文件的错误报告
+            let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut buffer = Printer::new(Vec::new(), &config, with_filename);
+                let result = search(&config, file, &mut buffer);
+                (buffer, result)
+            }));
+            let (buffer, result) = searched.unwrap_or_else(|payload| {
+                let message = format!("{}: search panicked: {}", name, panic_message(&*payload));
+                (
+                    Printer::new(Vec::new(), &config, with_filename),
+                    Err(SearchError::File(message)),
+                )
+            });
+            // 接收端只会在所有结果都收到之后才关闭，这里发送失败可以忽略
+            let _ = sender.send((index, buffer, result));
+        });
+    }
+    // 只保留 worker 手里的 sender，全部任务结束后 receiver 的迭代才会结束
+    drop(sender);
+
+    // 先完成的文件暂存起来，等排在它前面的文件都输出之后再输出
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    let mut outcome = Outcome::default();
+    for (index, buffer, result) in receiver {
+        pending.insert(index, (buffer, result));
+        while let Some((buffer, result)) = pending.remove(&next) {
+            printer.append(buffer)?;
+            outcome.record(result)?;
+            next += 1;
+        }
+    }
+
+    Ok(outcome)
+}
+
+// panic 的参数通常是 &str 或者 String
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    // 大小各不相同的文件，慢的文件和快的文件交替出现
+    fn corpus(dir: &Path) {
+        fs::create_dir_all(dir).unwrap();
+        for i in 0..40 {
+            let lines = (i * 97) % 400 + 1;
+            let text: String = (0..lines)
+                .map(|n| {
+                    format!(
+                        "file {} line {}{}\n",
+                        i,
+                        n,
+                        if n % 3 == 0 { " x" } else { "" }
+                    )
+                })
+                .collect();
+            fs::write(dir.join(format!("f{:02}.txt", i)), text).unwrap();
+        }
+    }
+
+    fn search(dir: &Path, threads: usize, search: Search) -> (String, Outcome) {
+        let argv = vec!["minigrep", "-n", "-A1", "x", dir.to_str().unwrap()];
+        let mut config = Config::new(argv.into_iter().map(String::from)).unwrap();
+        config.threads = threads;
+        let files = walk::walk(&config.paths, &config.filter);
+        let mut printer = Printer::new(Vec::new(), &config, true);
+        let outcome = if threads == 1 {
+            let mut outcome = Outcome::default();
+            for file in files {
+                outcome.record(search(&config, file, &mut printer)).unwrap();
+            }
+            outcome
+        } else {
+            search_with(config, files, true, &mut printer, search).unwrap()
+        };
+        (String::from_utf8(printer.into_inner()).unwrap(), outcome)
+    }
+
+    #[test]
+    fn output_matches_serial_order() {
+        let dir = env::temp_dir().join(format!("minigrep-parallel-{}", std::process::id()));
+        corpus(&dir);
+
+        let (serial, _) = search(&dir, 1, search_target);
+        let (parallel, outcome) = search(&dir, 8, search_target);
+        assert_eq!(parallel, serial);
+        assert!(outcome.matched);
+        // 每个文件的行连续出现，文件按名字顺序排列
+        let files: Vec<&str> = serial
+            .lines()
+            .filter(|line| *line != "--")
+            .map(|line| &line.strip_prefix(dir.to_str().unwrap()).unwrap()[1..8])
+            .collect();
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(files, sorted);
+        assert_eq!(files.first(), Some(&"f00.txt"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn panicking_search_is_reported_and_later_files_still_print() {
+        let dir = env::temp_dir().join(format!("minigrep-panic-{}", std::process::id()));
+        corpus(&dir);
+
+        fn flaky(
+            config: &Config,
+            file: Result<PathBuf, WalkError>,
+            printer: &mut Printer<Vec<u8>>,
+        ) -> Result<usize, SearchError> {
+            if file.as_ref().is_ok_and(|path| path.ends_with("f03.txt")) {
+                panic!("boom");
+            }
+            search_target(config, file, printer)
+        }
+        let (output, outcome) = search(&dir, 8, flaky);
+        assert_eq!(outcome.failed, 1);
+        assert!(!output.contains("f03.txt"));
+        assert!(output.contains("f39.txt"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+// 根据选项从查询构造出实际使用的匹配方式：正则、单个字面量或多个字面量
+use crate::fuzzy::Fuzzy;
+use crate::literal::Literal;
+use crate::multi::MultiLiteral;
+use crate::{ConfigError, Matcher, Span};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    // 每个模式各自编译成一个正则，这样不同模式里同名的捕获组互不冲突；
+    // set 用来一次判断一行里有没有任何一个模式匹配
+    Regex { regexes: Vec<Regex>, set: RegexSet },
+    Literal(Literal),
+    Multi(MultiLiteral),
+    Fuzzy(Fuzzy),
+}
+
+impl Pattern {
+    pub fn new(
+        patterns: &[String],
+        use_regex: bool,
+        case_sensitive: bool,
+        word: bool,
+        fuzzy: Option<usize>,
+    ) -> Result<Pattern, ConfigError> {
+        // Config 保证 --fuzzy 时正好有一个模式
+        if let (Some(max_distance), [query]) = (fuzzy, patterns) {
+            return Ok(Pattern::Fuzzy(Fuzzy::new(
+                query,
+                max_distance,
+                case_sensitive,
+            )));
+        }
+
+        // 一个模式都没有（例如 -f 指向空文件）时什么都不匹配，交给空的自动机处理
+        if use_regex && !patterns.is_empty() {
+            // 正则模式下 -w 借助正则的 \b 实现，普通查询则按 Unicode 单词边界判断
+            let wrap = |p: &str| {
+                if word {
+                    format!(r"\b(?:{})\b", p)
+                } else {
+                    p.to_string()
+                }
+            };
+            let patterns: Vec<String> = patterns.iter().map(|p| wrap(p)).collect();
+            let regexes = patterns
+                .iter()
+                .map(|p| {
+                    RegexBuilder::new(p)
+                        .case_insensitive(!case_sensitive)
+                        .build()
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let set = RegexSetBuilder::new(&patterns)
+                .case_insensitive(!case_sensitive)
+                .build()?;
+            return Ok(Pattern::Regex { regexes, set });
+        }
+
+        if patterns.len() == 1 {
+            return Ok(Pattern::Literal(Literal::new(
+                &patterns[0],
+                case_sensitive,
+                word,
+            )));
+        }
+
+        Ok(Pattern::Multi(MultiLiteral::new(
+            patterns,
+            case_sensitive,
+            word,
+        )?))
+    }
+}
+
+impl Matcher for Pattern {
+    fn is_match(&self, line: &str) -> bool {
+        match self {
+            Pattern::Regex { regexes, .. } if regexes.len() == 1 => regexes[0].is_match(line),
+            Pattern::Regex { set, .. } => set.is_match(line),
+            Pattern::Literal(literal) => literal.is_match(line),
+            Pattern::Multi(multi) => multi.is_match(line),
+            Pattern::Fuzzy(fuzzy) => fuzzy.is_match(line),
+        }
+    }
+
+    fn find_iter(&self, line: &str) -> Vec<Span> {
+        match self {
+            Pattern::Regex { regexes, .. } if regexes.len() == 1 => {
+                Matcher::find_iter(&regexes[0], line)
+            }
+            Pattern::Regex { regexes, set } => find_any(regexes, set, line),
+            Pattern::Literal(literal) => literal.find_iter(line),
+            Pattern::Multi(multi) => multi.find_iter(line),
+            Pattern::Fuzzy(fuzzy) => fuzzy.find_iter(line),
+        }
+    }
+
+    fn within_lines(&self) -> bool {
+        match self {
+            Pattern::Literal(literal) => literal.within_lines(),
+            Pattern::Multi(multi) => multi.within_lines(),
+            Pattern::Regex { .. } | Pattern::Fuzzy(_) => false,
+        }
+    }
+
+    // 正则模式下可以用 $1、${name} 引用捕获组，多个模式时引用的是命中的那个模式自己的捕获组
+    fn replace(&self, line: &str, replacement: &str) -> String {
+        match self {
+            Pattern::Regex { regexes, .. } if regexes.len() == 1 => {
+                Matcher::replace(&regexes[0], line, replacement)
+            }
+            Pattern::Regex { regexes, set } => {
+                let mut replaced = String::with_capacity(line.len());
+                let mut end = 0;
+                for span in find_any(regexes, set, line) {
+                    // 从匹配的起点开始找，得到的就是同一个匹配
+                    let caps = regexes[span.pattern].captures_at(line, span.start).unwrap();
+                    replaced.push_str(&line[end..span.start]);
+                    caps.expand(replacement, &mut replaced);
+                    end = span.end;
+                }
+                replaced.push_str(&line[end..]);
+                replaced
+            }
+            Pattern::Literal(literal) => literal.replace(line, replacement),
+            Pattern::Multi(multi) => multi.replace(line, replacement),
+            Pattern::Fuzzy(fuzzy) => fuzzy.replace(line, replacement),
+        }
+    }
+}
+
+// 合并多个正则在行内的匹配，从左到右且不重叠
+// 起点相同时取 -e 中靠前的模式，和把它们写成一个 a|b 正则时的选择一样
+fn find_any(regexes: &[Regex], set: &RegexSet, line: &str) -> Vec<Span> {
+    // 只在这一行能匹配的模式里找
+    let candidates: Vec<usize> = set.matches(line).into_iter().collect();
+    let mut spans = Vec::new();
+    let mut at = 0;
+    let mut last_end = None;
+    while at <= line.len() {
+        let next = candidates
+            .iter()
+            .filter_map(|&i| {
+                regexes[i]
+                    .find_at(line, at)
+                    .map(|m| (m.start(), i, m.end()))
+            })
+            .min_by_key(|&(start, i, _)| (start, i));
+        let (start, pattern, end) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        // 和 Regex::find_iter 一样，紧跟在上一个匹配后面的空匹配不算，从下一个字符重新找
+        if start == end && last_end == Some(end) {
+            match line[end..].chars().next() {
+                Some(c) => at = end + c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+        spans.push(Span {
+            start,
+            end,
+            pattern,
+        });
+        last_end = Some(end);
+        at = end;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_alternatives_report_pattern() {
+        let patterns = vec![r"\d+".to_string(), "[a-z]+".to_string()];
+        let pattern = Pattern::new(&patterns, true, true, false, None).unwrap();
+        let spans: Vec<_> = pattern
+            .find_iter("abc 42")
+            .into_iter()
+            .map(|s| (s.start, s.end, s.pattern))
+            .collect();
+        assert_eq!(spans, vec![(0, 3, 1), (4, 6, 0)]);
+    }
+
+    #[test]
+    fn replace_with_capture_groups() {
+        let patterns = vec![r"(?P<key>\w+)=(\d+)".to_string()];
+        let pattern = Pattern::new(&patterns, true, true, false, None).unwrap();
+        assert_eq!(pattern.replace("a=1, b=2", "$2:${key}"), "1:a, 2:b");
+
+        let pattern = Pattern::new(&["rust".to_string()], false, false, false, None).unwrap();
+        assert_eq!(pattern.replace("Rust, RUST", "$1"), "$1, $1");
+    }
+
+    #[test]
+    fn patterns_keep_their_own_group_names() {
+        let patterns = vec![
+            "(?P<p0>a)".to_string(),
+            r"(?P<x>b)(\d)".to_string(),
+            r"(?P<x>c)(\d)".to_string(),
+        ];
+        let pattern = Pattern::new(&patterns, true, true, false, None).unwrap();
+        let spans: Vec<_> = pattern
+            .find_iter("c1 a b2")
+            .into_iter()
+            .map(|s| (s.start, s.end, s.pattern))
+            .collect();
+        assert_eq!(spans, vec![(0, 2, 2), (3, 4, 0), (5, 7, 1)]);
+        assert_eq!(pattern.replace("c1 a b2", "[${x}$2]"), "[c1] [] [b2]");
+    }
+
+    #[test]
+    fn empty_matches_follow_find_iter() {
+        let patterns = vec!["x*".to_string(), "y".to_string()];
+        let pattern = Pattern::new(&patterns, true, true, false, None).unwrap();
+        let regex = Regex::new("x*|y").unwrap();
+        for line in &["", "ab", "xxa", "ayé", "xy"] {
+            let expected: Vec<_> = regex
+                .find_iter(line)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            let spans: Vec<_> = pattern
+                .find_iter(line)
+                .into_iter()
+                .map(|s| (s.start, s.end))
+                .collect();
+            assert_eq!(spans, expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn no_patterns_match_nothing() {
+        let pattern = Pattern::new(&[], true, true, false, None).unwrap();
+        assert!(!pattern.is_match("anything"));
+    }
+}
@@ -0,0 +1,413 @@
+// 负责把搜索结果按 grep 的格式打印出来
+// 匹配行用 : 分隔前缀，上下文行用 - 分隔，不相邻的两组输出之间打印 --
+// --json 时改为每行输出一个 JSON 对象（JSON Lines），方便其他程序解析
+// 开启颜色时和 GNU grep 的默认配色一致：文件名紫色、行号绿色、分隔符青色、匹配部分加粗红色
+use crate::searcher::Sink;
+use crate::{Config, Line, Span};
+use serde_json::json;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+const PATH_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const DIFF_HEADER_COLOR: &str = "\x1b[1m";
+const DELETED_COLOR: &str = "\x1b[31m";
+const INSERTED_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+// --json 的 summary 记录中输出的统计信息
+#[derive(Debug, Default, Clone, Copy)]
+struct Stats {
+    searched: usize,
+    matched_files: usize,
+    matched_lines: usize,
+    matches: usize,
+}
+
+pub struct Printer<W> {
+    out: W,
+    json: bool,
+    color: bool,
+    with_filename: bool,
+    line_number: bool,
+    byte_offset: bool,
+    context: bool,
+    // 是否已经打印过内容，用来决定下一组上下文前面要不要加 --
+    printed: bool,
+    // 正在打印的文件，以及其中最后打印的行号，换文件时重置
+    path: PathBuf,
+    last: Option<usize>,
+    // 当前文件的统计，以及是否已经输出过 begin 记录
+    file: Stats,
+    begun: bool,
+    total: Stats,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, config: &Config, with_filename: bool) -> Printer<W> {
+        Printer {
+            out,
+            json: config.json,
+            // JSON 是给程序读的，不输出颜色
+            color: !config.json && config.color.enabled(),
+            with_filename,
+            line_number: config.line_number,
+            byte_offset: config.byte_offset,
+            context: config.before_context > 0 || config.after_context > 0,
+            printed: false,
+            path: PathBuf::new(),
+            last: None,
+            file: Stats::default(),
+            begun: false,
+            total: Stats::default(),
+        }
+    }
+
+    // 修改是否在每行前面显示路径，返回原来的设置
+    pub fn set_with_filename(&mut self, with_filename: bool) -> bool {
+        std::mem::replace(&mut self.with_filename, with_filename)
+    }
+
+    // 把另一个 Printer 缓冲的输出追加到这里，用于并行搜索时按顺序合并各个文件的结果
+    pub fn append(&mut self, other: Printer<Vec<u8>>) -> io::Result<()> {
+        if self.context && self.printed && other.printed {
+            self.print_group_separator()?;
+        }
+        self.printed |= other.printed;
+        self.total.searched += other.total.searched;
+        self.total.matched_files += other.total.matched_files;
+        self.total.matched_lines += other.total.matched_lines;
+        self.total.matches += other.total.matches;
+        self.out.write_all(&other.out)
+    }
+
+    // 开始打印一个新文件，之后作为 Sink 收到的行都属于这个文件
+    pub fn begin_file(&mut self, path: &Path) {
+        self.path = path.to_path_buf();
+        self.last = None;
+        self.file = Stats::default();
+        self.begun = false;
+    }
+
+    // 一个文件搜索结束，--json 时如果输出过 begin 记录就输出对应的 end 记录
+    pub fn end_file(&mut self) -> io::Result<()> {
+        self.total.searched += 1;
+        if self.file.matched_lines > 0 {
+            self.total.matched_files += 1;
+        }
+        self.total.matched_lines += self.file.matched_lines;
+        self.total.matches += self.file.matches;
+
+        if self.json && self.begun {
+            self.write_json(json!({
+                "type": "end",
+                "path": self.path.display().to_string(),
+                "matched_lines": self.file.matched_lines,
+                "matches": self.file.matches,
+            }))?;
+        }
+        Ok(())
+    }
+
+    // 全部文件搜索结束，--json 时输出 summary 记录
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.json {
+            self.write_json(json!({
+                "type": "summary",
+                "searched_files": self.total.searched,
+                "matched_files": self.total.matched_files,
+                "matched_lines": self.total.matched_lines,
+                "matches": self.total.matches,
+            }))?;
+        }
+        self.out.flush()
+    }
+
+    // --watch 每检查一轮就把输出交出去，不等到结束
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    // spans 是匹配行中每个匹配的范围，上下文行传入空切片
+    fn print(&mut self, line: &Line, is_match: bool, spans: &[Span]) -> io::Result<()> {
+        if is_match {
+            self.file.matched_lines += 1;
+            self.file.matches += spans.len();
+        }
+
+        // 暂时把路径取出来，避免和 &mut self 的借用冲突
+        let path = mem::take(&mut self.path);
+        let result = if self.json {
+            self.print_json(&path, line, is_match, spans)
+        } else if is_match {
+            self.print_line(&path, line, ':', spans)
+        } else {
+            self.print_line(&path, line, '-', spans)
+        };
+        self.path = path;
+        result
+    }
+
+    pub fn print_count(&mut self, path: &Path, count: usize) -> io::Result<()> {
+        if self.with_filename {
+            let path = self.paint(PATH_COLOR, &path.display().to_string());
+            let separator = self.paint(SEPARATOR_COLOR, ":");
+            writeln!(self.out, "{}{}{}", path, separator, count)
+        } else {
+            writeln!(self.out, "{}", count)
+        }
+    }
+
+    // --replace 的 diff 预览，按行的第一个字符决定颜色
+    pub fn print_diff(&mut self, diff: &[String]) -> io::Result<()> {
+        for line in diff {
+            let color = if line.starts_with("---") || line.starts_with("+++") {
+                DIFF_HEADER_COLOR
+            } else if line.starts_with("@@") {
+                SEPARATOR_COLOR
+            } else if line.starts_with('-') {
+                DELETED_COLOR
+            } else if line.starts_with('+') {
+                INSERTED_COLOR
+            } else {
+                ""
+            };
+            let line = if color.is_empty() {
+                line.to_string()
+            } else {
+                self.paint(color, line)
+            };
+            writeln!(self.out, "{}", line)?;
+        }
+        self.printed = true;
+        Ok(())
+    }
+
+    pub fn print_path(&mut self, path: &Path) -> io::Result<()> {
+        let path = self.paint(PATH_COLOR, &path.display().to_string());
+        writeln!(self.out, "{}", path)
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // 开启颜色时用 ANSI 转义序列包住 text，否则原样返回
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn print_group_separator(&mut self) -> io::Result<()> {
+        let separator = self.paint(SEPARATOR_COLOR, "--");
+        writeln!(self.out, "{}", separator)
+    }
+
+    fn print_json(
+        &mut self,
+        path: &Path,
+        line: &Line,
+        is_match: bool,
+        spans: &[Span],
+    ) -> io::Result<()> {
+        let path = path.display().to_string();
+        if !self.begun {
+            self.begun = true;
+            self.write_json(json!({ "type": "begin", "path": path }))?;
+        }
+
+        let spans: Vec<_> = spans
+            .iter()
+            .map(|span| {
+                json!({
+                    "start": span.start,
+                    "end": span.end,
+                    "text": &line.text[span.start..span.end],
+                    "pattern": span.pattern,
+                })
+            })
+            .collect();
+        self.write_json(json!({
+            "type": if is_match { "match" } else { "context" },
+            "path": path,
+            "line_number": line.number,
+            "byte_offset": line.offset,
+            "text": line.text,
+            "spans": spans,
+        }))
+    }
+
+    fn write_json(&mut self, value: serde_json::Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &value)?;
+        writeln!(self.out)
+    }
+
+    fn print_line(
+        &mut self,
+        path: &Path,
+        line: &Line,
+        separator: char,
+        spans: &[Span],
+    ) -> io::Result<()> {
+        // 和上一行不相邻（包括换了文件）时说明是新的一组
+        if self.context && self.printed && self.last.map(|last| last + 1) != Some(line.number) {
+            self.print_group_separator()?;
+        }
+
+        let separator = self.paint(SEPARATOR_COLOR, &separator.to_string());
+        let mut prefix = String::new();
+        if self.with_filename {
+            prefix.push_str(&self.paint(PATH_COLOR, &path.display().to_string()));
+            prefix.push_str(&separator);
+        }
+        if self.line_number {
+            prefix.push_str(&self.paint(NUMBER_COLOR, &line.number.to_string()));
+            prefix.push_str(&separator);
+        }
+        if self.byte_offset {
+            prefix.push_str(&self.paint(NUMBER_COLOR, &line.offset.to_string()));
+            prefix.push_str(&separator);
+        }
+
+        let mut text = String::with_capacity(line.text.len());
+        let mut end = 0;
+        for span in spans {
+            text.push_str(&line.text[end..span.start]);
+            text.push_str(&self.paint(MATCH_COLOR, &line.text[span.start..span.end]));
+            end = span.end;
+        }
+        text.push_str(&line.text[end..]);
+
+        self.printed = true;
+        self.last = Some(line.number);
+        writeln!(self.out, "{}{}", prefix, text)
+    }
+}
+
+// CLI 的输出也只是 Sink 的一种实现
+impl<W: Write> Sink for Printer<W> {
+    fn matched(&mut self, line: &Line, spans: &[Span]) -> io::Result<bool> {
+        self.print(line, true, spans)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<bool> {
+        self.print(line, false, &[])?;
+        Ok(true)
+    }
+
+    // 只在需要输出匹配位置时才计算匹配的范围
+    fn needs_spans(&self) -> bool {
+        self.json || self.color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(args: &[&str], contents: &str) -> String {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|s| s.to_string()));
+        argv.extend(vec!["x".to_string(), "poem.txt".to_string()]);
+        let config = Config::new(argv.into_iter()).unwrap();
+
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        for _ in 0..2 {
+            printer.begin_file(Path::new("poem.txt"));
+            config
+                .searcher()
+                .search_str(&config.matcher, contents, &mut printer)
+                .unwrap();
+            printer.end_file().unwrap();
+        }
+        printer.finish().unwrap();
+        String::from_utf8(printer.out).unwrap()
+    }
+
+    #[test]
+    fn json_lines() {
+        let output = print(&["--json", "-A1"], "a\nb x\nc\n");
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let types: Vec<_> = records
+            .iter()
+            .map(|r| r["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["begin", "match", "context", "end", "begin", "match", "context", "end", "summary"]
+        );
+        assert_eq!(
+            records[1],
+            json!({
+                "type": "match",
+                "path": "poem.txt",
+                "line_number": 2,
+                "byte_offset": 2,
+                "text": "b x",
+                "spans": [{ "start": 2, "end": 3, "text": "x", "pattern": 0 }],
+            })
+        );
+        assert_eq!(records[8]["searched_files"], 2);
+        assert_eq!(records[8]["matched_lines"], 2);
+    }
+
+    #[test]
+    fn append_keeps_group_separator() {
+        let argv = vec!["minigrep", "-A1", "x", "a", "b"];
+        let config = Config::new(argv.into_iter().map(String::from)).unwrap();
+        let line = Line {
+            number: 1,
+            offset: 0,
+            text: "x",
+        };
+
+        let mut printer = Printer::new(Vec::new(), &config, true);
+        for path in &["a", "b"] {
+            let mut buffer = Printer::new(Vec::new(), &config, true);
+            buffer.begin_file(Path::new(path));
+            buffer.matched(&line, &[]).unwrap();
+            printer.append(buffer).unwrap();
+        }
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "a:x\n--\nb:x\n");
+    }
+
+    #[test]
+    fn line_numbers_and_offsets() {
+        assert_eq!(
+            print(&["-nb"], "one\ntwo x\r\nthree x\n"),
+            "2:4:two x\n3:11:three x\n2:4:two x\n3:11:three x\n"
+        );
+    }
+
+    #[test]
+    fn colored_output() {
+        assert_eq!(
+            print(&["--color=always", "-n"], "a x\n"),
+            "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0ma \x1b[1;31mx\x1b[0m\n".repeat(2)
+        );
+        // 默认的 auto 在测试中不是终端，不输出颜色
+        assert_eq!(print(&["-n"], "a x\n"), "1:a x\n1:a x\n");
+    }
+
+    #[test]
+    fn context_groups_are_separated() {
+        let contents = "a\nb x\nc\nd x\ne\nf\ng\nh x\n";
+        // 第 2 行和第 4 行的窗口重叠，合并成一组；第 8 行单独一组；第二个文件前面也要加 --
+        assert_eq!(
+            print(&["-n", "-C1"], contents),
+            "1-a\n2:b x\n3-c\n4:d x\n5-e\n--\n7-g\n8:h x\n\
+             --\n1-a\n2:b x\n3-c\n4:d x\n5-e\n--\n7-g\n8:h x\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// 配置文件：用户级的 ~/.config/minigrep/config.toml（设置了 XDG_CONFIG_HOME 时在它下面，
+// MINIGREP_CONFIG 可以指定别的文件，设为空表示不读）和项目里的 .minigreprc（从当前目录向上找最近的一个）
+// 两者格式相同，项目配置后读；配置文件里的选项和命令行分开解析、先于命令行应用，所以命令行上的选项总是优先
+//
+//     flags = ["--line-number", "--threads=4"]
+//     ignore = ["target", "*.min.js"]
+//     color = "always"
+//
+//     [types]
+//     web = ["*.html", "*.css", "*.js"]
+use crate::config::parse_args;
+use crate::ConfigError;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 内置的 --type，配置文件中的同名类型会替换它
+const BUILTIN_TYPES: [(&str, &[&str]); 12] = [
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.h"]),
+    ("csv", &["*.csv"]),
+    ("go", &["*.go"]),
+    ("js", &["*.js", "*.mjs", "*.cjs"]),
+    ("json", &["*.json", "*.jsonl", "*.ndjson"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("toml", &["*.toml"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+const PROJECT_FILE: &str = ".minigreprc";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProfileFile {
+    // 和命令行上一样的选项，不能有位置参数
+    flags: Vec<String>,
+    // 相当于每一个都传给 --exclude
+    ignore: Vec<String>,
+    color: Option<String>,
+    types: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    // 读取过的配置文件，按读取顺序排列
+    pub sources: Vec<PathBuf>,
+    // 配置文件转换成的命令行选项，每个配置文件的部分都是完整的，不会把后面的参数当作自己的值
+    pub args: Vec<String>,
+    // --type 的名字到文件名 glob 的映射
+    pub types: BTreeMap<String, Vec<String>>,
+}
+
+impl Profile {
+    // 只有内置类型，不读任何配置文件
+    pub fn builtin() -> Profile {
+        Profile {
+            sources: Vec::new(),
+            args: Vec::new(),
+            types: BUILTIN_TYPES
+                .iter()
+                .map(|(name, globs)| {
+                    (
+                        name.to_string(),
+                        globs.iter().map(|g| g.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    // 依次读取用户配置和项目配置，不存在的文件直接跳过
+    pub fn load() -> Result<Profile, ConfigError> {
+        let mut profile = Profile::builtin();
+        let project = env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .map(|dir| dir.join(PROJECT_FILE))
+                .find(|path| path.is_file())
+        });
+        for path in user_config().into_iter().chain(project) {
+            if !path.is_file() {
+                continue;
+            }
+            let text = fs::read_to_string(&path)
+                .map_err(|e| ConfigError::ConfigFile(path.clone(), e.to_string()))?;
+            profile.merge(&path, &text)?;
+        }
+        Ok(profile)
+    }
+
+    pub fn merge(&mut self, path: &Path, text: &str) -> Result<(), ConfigError> {
+        let error = |message: String| ConfigError::ConfigFile(path.to_path_buf(), message);
+        let file: ProfileFile = toml::from_str(text).map_err(|e| error(e.message().to_string()))?;
+
+        // 单独解析这个文件的 flags：带参数的选项必须在这里给出参数，而且不能有位置参数
+        if let Some(flag) = file.flags.iter().find(|f| *f == "--") {
+            return Err(error(format!("flags must be options, found {:?}", flag)));
+        }
+        let (_, positional) = parse_args(&file.flags).map_err(|e| error(e.to_string()))?;
+        if let Some(flag) = positional.first() {
+            return Err(error(format!("flags must be options, found {:?}", flag)));
+        }
+        self.args.extend(file.flags);
+        self.args
+            .extend(file.ignore.iter().map(|glob| format!("--exclude={}", glob)));
+        if let Some(color) = file.color {
+            self.args.push(format!("--color={}", color));
+        }
+        self.types.extend(file.types);
+        self.sources.push(path.to_path_buf());
+        Ok(())
+    }
+}
+
+fn user_config() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("MINIGREP_CONFIG") {
+        return if path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(path))
+        };
+    }
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("minigrep").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_in_order() {
+        let mut profile = Profile::builtin();
+        profile
+            .merge(
+                Path::new("user.toml"),
+                "flags = [\"-n\"]\ncolor = \"always\"\n[types]\nweb = [\"*.html\"]\n",
+            )
+            .unwrap();
+        profile
+            .merge(
+                Path::new(".minigreprc"),
+                "ignore = [\"target\"]\ncolor = \"never\"\n[types]\nrust = [\"*.rs\", \"*.rs.in\"]\n",
+            )
+            .unwrap();
+        assert_eq!(
+            profile.args,
+            vec!["-n", "--color=always", "--exclude=target", "--color=never"]
+        );
+        assert_eq!(profile.types["web"], vec!["*.html"]);
+        assert_eq!(profile.types["rust"], vec!["*.rs", "*.rs.in"]);
+        assert_eq!(profile.sources.len(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let mut profile = Profile::builtin();
+        for text in [
+            "flags = [\"src\"]",
+            "colour = \"always\"",
+            "flags = ",
+            // 缺少参数的选项不能吃掉后面的内容
+            "flags = [\"--include\"]",
+            "flags = [\"-n\", \"-A\"]\nignore = [\"target\"]",
+        ] {
+            assert!(matches!(
+                profile.merge(Path::new("bad.toml"), text),
+                Err(ConfigError::ConfigFile(_, _))
+            ));
+        }
+        assert!(profile.args.is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+// --replace：把匹配行里的每个匹配替换掉
+// 默认只输出 unified diff 预览，文件不会被修改；加上 --write 才会改写文件，
+// 改写时先写到同一目录下的临时文件再 rename 过去，中途出错也不会留下写了一半的文件
+use crate::printer::Printer;
+use crate::{Config, Matcher};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// diff 中每处修改前后保留的上下文行数，和 diff -u 一样
+const DIFF_CONTEXT: usize = 3;
+
+// 文件中的一行，替换只作用于换行符之前的部分，所以替换前后的行一一对应
+struct Row<'a> {
+    old: &'a str,
+    // 替换后有变化时才有值
+    new: Option<String>,
+    // 行尾的 \n 或 \r\n，最后一行可能没有
+    ending: &'a str,
+}
+
+// 替换 contents 中的匹配，预览或改写 path，返回匹配的行数
+// writable 为 false 时（标准输入）不能使用 --write
+pub fn replace<W: Write>(
+    config: &Config,
+    path: &Path,
+    writable: bool,
+    contents: &str,
+    printer: &mut Printer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    // Config 保证 replace 模式下一定有替换文本
+    let replacement = config.replace.as_deref().unwrap_or_default();
+    let mut selected = 0;
+    let mut rows = Vec::new();
+    for raw in contents.split_inclusive('\n') {
+        let old = raw.strip_suffix('\n').unwrap_or(raw);
+        let old = old.strip_suffix('\r').unwrap_or(old);
+        let mut new = None;
+        if config.max_count != Some(selected) && config.matcher.is_match(old) {
+            selected += 1;
+            new = Some(config.matcher.replace(old, replacement)).filter(|new| new != old);
+        }
+        rows.push(Row {
+            old,
+            new,
+            ending: &raw[old.len()..],
+        });
+    }
+
+    if rows.iter().all(|row| row.new.is_none()) {
+        return Ok(selected);
+    }
+
+    if config.write {
+        if !writable {
+            return Err("can't write the result back to standard input".into());
+        }
+        let mut replaced = String::with_capacity(contents.len());
+        for row in &rows {
+            replaced.push_str(row.new.as_deref().unwrap_or(row.old));
+            replaced.push_str(row.ending);
+        }
+        write_atomic(path, &replaced, config.backup.as_deref())?;
+    } else {
+        printer.print_diff(&unified_diff(path, &rows))?;
+    }
+    Ok(selected)
+}
+
+// 生成 diff -u 格式的差异，每一行都不带换行符
+fn unified_diff(path: &Path, rows: &[Row]) -> Vec<String> {
+    let path = path.display();
+    let mut diff = vec![format!("--- {}", path), format!("+++ {}", path)];
+
+    // 把相距不超过两倍上下文的修改合并成同一个 hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, _) in rows.iter().enumerate().filter(|(_, row)| row.new.is_some()) {
+        let start = i.saturating_sub(DIFF_CONTEXT);
+        let end = (i + DIFF_CONTEXT + 1).min(rows.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        // 替换不会增删行，所以新旧两边的范围相同
+        diff.push(format!(
+            "@@ -{},{} +{},{} @@",
+            start + 1,
+            end - start,
+            start + 1,
+            end - start
+        ));
+        for row in &rows[start..end] {
+            let missing_newline = row.ending.is_empty();
+            match &row.new {
+                Some(new) => {
+                    diff.push(format!("-{}", row.old));
+                    if missing_newline {
+                        diff.push("\\ No newline at end of file".to_string());
+                    }
+                    diff.push(format!("+{}", new));
+                }
+                None => diff.push(format!(" {}", row.old)),
+            }
+            if missing_newline {
+                diff.push("\\ No newline at end of file".to_string());
+            }
+        }
+    }
+    diff
+}
+
+// 写到同一目录下的临时文件，同步到磁盘后 rename 覆盖原文件；rename 在同一文件系统内是原子的
+// 有 backup 后缀时先把原文件复制一份
+fn write_atomic(path: &Path, contents: &str, backup: Option<&str>) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.minigrep-{}", name, process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        // 保留原文件的权限，例如可执行位
+        file.set_permissions(fs::metadata(path)?.permissions())?;
+        file.sync_all()?;
+        if let Some(suffix) = backup {
+            fs::copy(path, backup_path(path, suffix))?;
+        }
+        fs::rename(&temp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(suffix);
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Config {
+        let mut argv = vec!["minigrep"];
+        argv.extend(args);
+        Config::new(argv.into_iter().map(String::from)).unwrap()
+    }
+
+    fn preview(args: &[&str], contents: &str) -> String {
+        let config = config(args);
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        replace(&config, Path::new("a.txt"), true, contents, &mut printer).unwrap();
+        printer.finish().unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn diff_preview() {
+        let contents = "1\n2\nfoo=1\n4\n5\n6\n7\n8\n9\n10\nfoo=2";
+        assert_eq!(
+            preview(&["-E", "--replace", "bar=$1", r"foo=(\d)"], contents),
+            "--- a.txt\n+++ a.txt\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-foo=1\n+bar=1\n 4\n 5\n 6\n\
+             @@ -8,4 +8,4 @@\n 8\n 9\n 10\n-foo=2\n\\ No newline at end of file\n\
+             +bar=2\n\\ No newline at end of file\n"
+        );
+        // 替换结果和原文相同时没有任何输出
+        assert_eq!(preview(&["--replace", "foo", "foo"], contents), "");
+    }
+
+    #[test]
+    fn write_in_place_with_backup() {
+        let dir = std::env::temp_dir().join(format!("minigrep-replace-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "Hello world\r\nbye\n").unwrap();
+
+        let config = config(&["-i", "--replace=Rust", "--write", "--backup=.orig", "WORLD"]);
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            replace(&config, &path, true, &contents, &mut printer).unwrap(),
+            1
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Hello Rust\r\nbye\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("a.txt.orig")).unwrap(),
+            "Hello world\r\nbye\n"
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(printer.into_inner().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,462 @@
+// 流式搜索：一次只读一行，不需要把整个文件读进内存，所以也可以用在管道和标准输入上
+// 结果通过回调逐条交出去，调用方可以边搜边打印
+// Searcher 负责怎么搜（上下文、-m、-v），Matcher 负责怎么匹配，Sink 负责怎么处理结果，
+// CLI 的输出只是 Sink 的一种实现，其他程序可以实现自己的 Sink 来嵌入搜索
+use crate::{Line, Matcher, Span};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+// 接收搜索结果，匹配行和上下文行按在文件中的先后顺序交给它
+// 返回 Ok(false) 表示不再需要更多结果，搜索会提前停止
+pub trait Sink {
+    // spans 是行内每个匹配的范围；needs_spans 返回 false 或 -v 时是空的
+    fn matched(&mut self, line: &Line, spans: &[Span]) -> io::Result<bool>;
+
+    fn context(&mut self, _line: &Line) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    // 不需要匹配范围时返回 false，可以省去查找范围的开销
+    fn needs_spans(&self) -> bool {
+        true
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn matched(&mut self, line: &Line, spans: &[Span]) -> io::Result<bool> {
+        (**self).matched(line, spans)
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<bool> {
+        (**self).context(line)
+    }
+
+    fn needs_spans(&self) -> bool {
+        (**self).needs_spans()
+    }
+}
+
+// 丢弃所有结果，只需要知道匹配的行数时使用
+pub struct Discard;
+
+impl Sink for Discard {
+    fn matched(&mut self, _line: &Line, _spans: &[Span]) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn needs_spans(&self) -> bool {
+        false
+    }
+}
+
+// 搜索的选项，默认没有上下文、不限制匹配行数、不反向匹配
+#[derive(Debug, Clone, Default)]
+pub struct Searcher {
+    before: usize,
+    after: usize,
+    max_count: Option<usize>,
+    invert: bool,
+    // 调用方已经读掉的行数和字节数，交给 Sink 的行号和偏移从这之后算起
+    skipped: (usize, usize),
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::default()
+    }
+
+    pub fn before_context(mut self, lines: usize) -> Searcher {
+        self.before = lines;
+        self
+    }
+
+    pub fn after_context(mut self, lines: usize) -> Searcher {
+        self.after = lines;
+        self
+    }
+
+    // 每个 reader 最多匹配的行数，None 表示不限制
+    pub fn max_count(mut self, max_count: Option<usize>) -> Searcher {
+        self.max_count = max_count;
+        self
+    }
+
+    // 选中不匹配的行
+    pub fn invert(mut self, invert: bool) -> Searcher {
+        self.invert = invert;
+        self
+    }
+
+    // reader 之前已经读掉了 lines 行、bytes 个字节（比如 CSV 的表头）
+    pub fn skipped(mut self, lines: usize, bytes: usize) -> Searcher {
+        self.skipped = (lines, bytes);
+        self
+    }
+
+    // 在 reader 中搜索 matcher，结果交给 sink，返回选中的行数
+    pub fn search_reader<M, R, S>(&self, matcher: &M, reader: R, mut sink: S) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        R: BufRead,
+        S: Sink,
+    {
+        let spans = sink.needs_spans() && !self.invert;
+        search_lines(
+            reader,
+            |line| matcher.is_match(line) != self.invert,
+            !self.invert && matcher.within_lines(),
+            self.before,
+            self.after,
+            self.max_count,
+            |event| match event {
+                Event::Match(line) if spans => {
+                    let found = matcher.find_iter(line.text);
+                    sink.matched(&self.shift(line), &found)
+                }
+                Event::Match(line) => sink.matched(&self.shift(line), &[]),
+                Event::Context(line) => sink.context(&self.shift(line)),
+            },
+        )
+    }
+
+    fn shift<'a>(&self, line: Line<'a>) -> Line<'a> {
+        let (lines, bytes) = self.skipped;
+        Line {
+            number: line.number + lines,
+            offset: line.offset + bytes,
+            ..line
+        }
+    }
+
+    pub fn search_str<M, S>(&self, matcher: &M, contents: &str, sink: S) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink,
+    {
+        self.search_reader(matcher, contents.as_bytes(), sink)
+    }
+}
+
+// 回调收到的事件，匹配行和上下文行按在文件中的先后顺序交出
+#[derive(Debug, PartialEq)]
+enum Event<'a> {
+    Match(Line<'a>),
+    Context(Line<'a>),
+}
+
+// 为了输出 -B 的上下文需要缓存最近的几行，缓存里的行必须拥有自己的数据
+struct OwnedLine {
+    number: usize,
+    offset: usize,
+    text: String,
+}
+
+// 一次整块检查的最大字节数，块中有匹配时要再逐行检查一遍，所以不宜太大
+const BLOCK_SIZE: usize = 32 * 1024;
+
+// Searcher 底层的实现：逐行读取 reader，is_match 返回 true 的行作为匹配行交给 emit，
+// before/after 是上下文的行数，返回匹配的行数；emit 返回 Ok(false) 时停止
+// max_count 是最多匹配的行数（-m），达到之后只再输出 after 行上下文就停止读取
+// 按原始字节读取，不是合法 UTF-8 的部分会被替换成 U+FFFD 再交给 is_match
+// blocks 为 true 时 is_match 也可以用在多行文本上（见 Matcher::within_lines），
+// 缓冲区中的一整块内容没有匹配时直接跳过，不必逐行检查；需要 -B 的上下文时不跳过
+fn search_lines<R, M, F>(
+    mut reader: R,
+    mut is_match: M,
+    blocks: bool,
+    before: usize,
+    after: usize,
+    max_count: Option<usize>,
+    mut emit: F,
+) -> io::Result<usize>
+where
+    R: BufRead,
+    M: FnMut(&str) -> bool,
+    F: FnMut(Event) -> io::Result<bool>,
+{
+    let mut buf = Vec::new();
+    let mut number = 0;
+    let mut offset = 0;
+    let mut count = 0;
+    // 只保存上一次输出之后还没有输出过的行，所以不会重复打印
+    let mut history: VecDeque<OwnedLine> = VecDeque::with_capacity(before);
+    let mut after_left = 0;
+    // 整块检查过、确定含有匹配，还需要逐行处理的字节数
+    let mut unchecked = 0;
+    if max_count == Some(0) {
+        return Ok(0);
+    }
+
+    loop {
+        if blocks && unchecked == 0 && before == 0 && after_left == 0 {
+            let skipped = {
+                let available = reader.fill_buf()?;
+                let window = &available[..available.len().min(BLOCK_SIZE)];
+                // 只检查完整的行；不是合法 UTF-8 的块交给下面逐行处理
+                match memchr::memrchr(b'\n', window) {
+                    Some(end) => match std::str::from_utf8(&window[..=end]) {
+                        Ok(text) if !is_match(text) => {
+                            Some((end + 1, memchr::memchr_iter(b'\n', text.as_bytes()).count()))
+                        }
+                        Ok(_) => {
+                            unchecked = end + 1;
+                            None
+                        }
+                        Err(_) => None,
+                    },
+                    None => None,
+                }
+            };
+            if let Some((n, lines)) = skipped {
+                reader.consume(n);
+                number += lines;
+                offset += n;
+                continue;
+            }
+        }
+
+        // 整行都在 reader 的缓冲区里时直接借用，不复制；跨过缓冲区边界的行才拷贝出来拼接
+        // reader 是内存映射的文件时整个文件都在缓冲区里
+        let newline = {
+            let available = reader.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            memchr::memchr(b'\n', available)
+        };
+        let (raw, n): (&[u8], usize) = match newline {
+            Some(i) => (&reader.fill_buf()?[..=i], i + 1),
+            None => {
+                buf.clear();
+                let n = reader.read_until(b'\n', &mut buf)?;
+                (&buf, n)
+            }
+        };
+        number += 1;
+
+        let bytes = raw.strip_suffix(b"\n").unwrap_or(raw);
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.as_ref();
+        let line = Line {
+            number,
+            offset,
+            text,
+        };
+
+        if max_count == Some(count) {
+            // 已经达到 -m 的上限，剩下的行（即使匹配）都只是上下文
+            if after_left == 0 {
+                break;
+            }
+            if !emit(Event::Context(line))? {
+                break;
+            }
+            after_left -= 1;
+        } else if is_match(text) {
+            count += 1;
+            for old in history.drain(..) {
+                let line = Line {
+                    number: old.number,
+                    offset: old.offset,
+                    text: &old.text,
+                };
+                if !emit(Event::Context(line))? {
+                    return Ok(count);
+                }
+            }
+            if !emit(Event::Match(line))? {
+                break;
+            }
+            after_left = after;
+        } else if after_left > 0 {
+            if !emit(Event::Context(line))? {
+                break;
+            }
+            after_left -= 1;
+        } else if before > 0 {
+            if history.len() == before {
+                history.pop_front();
+            }
+            history.push_back(OwnedLine {
+                number,
+                offset,
+                text: text.to_string(),
+            });
+        }
+
+        if newline.is_some() {
+            reader.consume(n);
+        }
+        offset += n;
+        unchecked = unchecked.saturating_sub(n);
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal::Literal;
+
+    // 按先后顺序记录每行的偏移，以及匹配行（行号:内容）和上下文行（行号-内容）
+    struct Events(Vec<(usize, String)>);
+
+    impl Sink for Events {
+        fn matched(&mut self, line: &Line, _spans: &[Span]) -> io::Result<bool> {
+            let event = format!("{}:{}", line.number, line.text);
+            self.0.push((line.offset, event));
+            Ok(true)
+        }
+
+        fn context(&mut self, line: &Line) -> io::Result<bool> {
+            let event = format!("{}-{}", line.number, line.text);
+            self.0.push((line.offset, event));
+            Ok(true)
+        }
+    }
+
+    fn collect(
+        contents: &str,
+        query: &str,
+        before: usize,
+        after: usize,
+        max_count: Option<usize>,
+    ) -> Vec<String> {
+        let mut events = Events(Vec::new());
+        Searcher::new()
+            .before_context(before)
+            .after_context(after)
+            .max_count(max_count)
+            .search_str(&Literal::new(query, true, false), contents, &mut events)
+            .unwrap();
+        events.0.into_iter().map(|(_, event)| event).collect()
+    }
+
+    #[test]
+    fn context_is_not_repeated() {
+        let contents = "a\nb x\nc\nd x\ne\nf\ng\nh x\n";
+        assert_eq!(
+            collect(contents, "x", 1, 1, None),
+            vec!["1-a", "2:b x", "3-c", "4:d x", "5-e", "7-g", "8:h x"]
+        );
+    }
+
+    #[test]
+    fn max_count_stops_after_context() {
+        let contents = "a x\nb x\nc\nd x\n";
+        assert_eq!(
+            collect(contents, "x", 0, 1, Some(1)),
+            vec!["1:a x", "2-b x"]
+        );
+        assert_eq!(
+            collect(contents, "x", 0, 0, Some(2)),
+            vec!["1:a x", "2:b x"]
+        );
+        assert!(collect(contents, "x", 0, 0, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn reads_from_any_bufread() {
+        let reader = io::BufReader::with_capacity(2, "one\ntwo x\r\nthree x".as_bytes());
+        let mut events = Events(Vec::new());
+        let count = Searcher::new()
+            .search_reader(&Literal::new("x", true, false), reader, &mut events)
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            events.0,
+            vec![(4, "2:two x".to_string()), (11, "3:three x".to_string())]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_lossy() {
+        let mut events = Events(Vec::new());
+        let count = Searcher::new()
+            .search_reader(
+                &Literal::new("caf\u{e9}", true, false),
+                &b"ok\n\xff\xfe caf\xc3\xa9\nend\n"[..],
+                &mut events,
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            events.0,
+            vec![(3, "2:\u{fffd}\u{fffd} caf\u{e9}".to_string())]
+        );
+    }
+
+    // 收集匹配的文本，最多收集 limit 行
+    struct Collect {
+        limit: usize,
+        found: Vec<String>,
+    }
+
+    impl Sink for Collect {
+        fn matched(&mut self, line: &Line, spans: &[Span]) -> io::Result<bool> {
+            for span in spans {
+                self.found.push(line.text[span.start..span.end].to_string());
+            }
+            Ok(self.found.len() < self.limit)
+        }
+    }
+
+    // 记录匹配行的行号和偏移
+    struct Positions(Vec<(usize, usize)>);
+
+    impl Sink for Positions {
+        fn matched(&mut self, line: &Line, _spans: &[Span]) -> io::Result<bool> {
+            self.0.push((line.number, line.offset));
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn skipped_blocks_keep_positions() {
+        let mut contents = String::new();
+        for i in 0..20_000 {
+            contents.push_str(if i % 7_919 == 0 {
+                "needle here\n"
+            } else {
+                "hay stack\n"
+            });
+        }
+        let literal = Literal::new("needle", true, false);
+        assert!(literal.within_lines());
+        let regex = regex::Regex::new("needle").unwrap();
+        assert!(!regex.within_lines());
+
+        let mut fast = Positions(Vec::new());
+        let mut slow = Positions(Vec::new());
+        Searcher::new()
+            .search_str(&literal, &contents, &mut fast)
+            .unwrap();
+        Searcher::new()
+            .search_str(&regex, &contents, &mut slow)
+            .unwrap();
+        assert_eq!(fast.0, vec![(1, 0), (7_920, 79_192), (15_839, 158_384)]);
+        assert_eq!(fast.0, slow.0);
+    }
+
+    #[test]
+    fn searcher_feeds_sink() {
+        let matcher = regex::Regex::new(r"\d+").unwrap();
+        let mut sink = Collect {
+            limit: 2,
+            found: Vec::new(),
+        };
+        let count = Searcher::new()
+            .search_str(&matcher, "a 1\nb\nc 22 333\nd 4\n", &mut sink)
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(sink.found, vec!["1", "22", "333"]);
+
+        let count = Searcher::new()
+            .invert(true)
+            .search_str(&matcher, "a 1\nb\nc\n", Discard)
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
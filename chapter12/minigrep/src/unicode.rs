@@ -0,0 +1,172 @@
+// Unicode 大小写折叠（case folding）和单词边界
+// to_lowercase 并不等于大小写折叠：例如 ß 的折叠结果是 ss，开尔文符号 K 折叠为 k，
+// 土耳其语的 İ 折叠为 i + U+0307，而无点的 ı 只和自己相等
+use unicode_segmentation::UnicodeSegmentation;
+
+// 一次 “先转大写再转小写”，大部分字符的折叠结果都可以这样得到
+fn fold_once(c: char) -> impl Iterator<Item = char> {
+    c.to_uppercase().flat_map(char::to_lowercase)
+}
+
+// 完全折叠（full case folding），一个字符可能折叠成多个字符，例如 ß -> ss
+// 做两次是为了让结果稳定下来：ẞ -> ß -> ss
+pub fn fold_full(c: char) -> Vec<char> {
+    // ı 的大写是 I，但按 Unicode 默认规则 ı 并不折叠成 i
+    if c == 'ı' {
+        return vec![c];
+    }
+    fold_once(c).flat_map(fold_once).collect()
+}
+
+// 简单折叠（simple case folding），一个字符总是折叠成一个字符，
+// 没有一对一折叠结果的字符保持不变，例如 ß 和 İ
+pub fn fold_simple(c: char) -> char {
+    match fold_full(c).as_slice() {
+        [folded] => *folded,
+        _ => {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(folded), None) => folded,
+                _ => c,
+            }
+        }
+    }
+}
+
+// 按 UAX #29 划分单词时所有边界所在的字节下标，包括 0 和 line.len()
+pub fn word_boundaries(line: &str) -> Vec<usize> {
+    let mut bounds: Vec<usize> = line.split_word_bound_indices().map(|(i, _)| i).collect();
+    bounds.push(line.len());
+    bounds
+}
+
+// 折叠后的一个字符，记录它来自原文的哪个字符，这样匹配范围总能换算回原文
+struct FoldedChar {
+    c: char,
+    start: usize,
+    end: usize,
+    // 是否是原文中某个字符折叠结果的第一个/最后一个字符
+    first: bool,
+    last: bool,
+}
+
+fn fold_line(line: &str) -> Vec<FoldedChar> {
+    let mut folded = Vec::with_capacity(line.len());
+    for (start, c) in line.char_indices() {
+        let end = start + c.len_utf8();
+        let chars = fold_full(c);
+        let n = chars.len();
+        for (i, f) in chars.into_iter().enumerate() {
+            folded.push(FoldedChar {
+                c: f,
+                start,
+                end,
+                first: i == 0,
+                last: i + 1 == n,
+            });
+        }
+    }
+    folded
+}
+
+// 大小写不敏感的字面量查找，查询只在创建时折叠一次
+#[derive(Debug, Clone)]
+pub struct FoldedQuery {
+    folded: Vec<char>,
+    // 查询全是 ASCII 时，遇到全是 ASCII 的行可以直接按字节比较
+    ascii: Option<Vec<u8>>,
+}
+
+impl FoldedQuery {
+    pub fn new(query: &str) -> FoldedQuery {
+        FoldedQuery {
+            folded: query.chars().flat_map(fold_full).collect(),
+            ascii: if query.is_ascii() {
+                Some(query.to_ascii_lowercase().into_bytes())
+            } else {
+                None
+            },
+        }
+    }
+
+    // 返回所有匹配（可能互相重叠），范围都指向原文并且落在字符边界上
+    // 匹配必须从原文某个字符折叠结果的开头开始、在某个字符折叠结果的末尾结束，
+    // 所以 s 不会匹配 ß 折叠结果 ss 的一半
+    pub fn candidates(&self, line: &str) -> Vec<(usize, usize)> {
+        if self.folded.is_empty() {
+            return Vec::new();
+        }
+
+        if let (Some(query), true) = (&self.ascii, line.is_ascii()) {
+            let bytes = line.as_bytes();
+            if bytes.len() < query.len() {
+                return Vec::new();
+            }
+            // 先用 memchr 找到首字母的大写或小写，再比较整个查询
+            let first = query[0];
+            let starts = &bytes[..=bytes.len() - query.len()];
+            return memchr::memchr2_iter(first, first.to_ascii_uppercase(), starts)
+                .filter(|&i| bytes[i..i + query.len()].eq_ignore_ascii_case(query))
+                .map(|i| (i, i + query.len()))
+                .collect();
+        }
+
+        let folded = fold_line(line);
+        let m = self.folded.len();
+        if folded.len() < m {
+            return Vec::new();
+        }
+        (0..=folded.len() - m)
+            .filter(|&i| {
+                folded[i].first
+                    && folded[i + m - 1].last
+                    && folded[i..i + m]
+                        .iter()
+                        .zip(&self.folded)
+                        .all(|(f, q)| f.c == *q)
+            })
+            .map(|i| (folded[i].start, folded[i + m - 1].end))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(query: &str, line: &str) -> Vec<(usize, usize)> {
+        FoldedQuery::new(query).candidates(line)
+    }
+
+    #[test]
+    fn full_and_simple_folding() {
+        assert_eq!(fold_full('ß'), vec!['s', 's']);
+        assert_eq!(fold_full('ẞ'), vec!['s', 's']);
+        assert_eq!(fold_full('İ'), vec!['i', '\u{307}']);
+        assert_eq!(fold_full('ı'), vec!['ı']);
+        assert_eq!(fold_full('ς'), vec!['σ']);
+        assert_eq!(fold_simple('ẞ'), 'ß');
+        assert_eq!(fold_simple('ß'), 'ß');
+        assert_eq!(fold_simple('\u{212A}'), 'k');
+    }
+
+    #[test]
+    fn folded_spans_point_into_original() {
+        assert_eq!(spans("STRASSE", "die Straße"), vec![(4, 11)]);
+        assert_eq!(spans("straße", "STRASSE"), vec![(0, 7)]);
+        // 不能只匹配 ß 的一半
+        assert!(spans("as", "straße").is_empty());
+        assert_eq!(spans("kb", "5 \u{212A}B"), vec![(2, 6)]);
+        assert_eq!(spans("ΣΑΣ", "σας"), vec![(0, 6)]);
+        // 土耳其语：I 和 ı 不相等，İ 只和 i̇ 相等
+        assert!(spans("i", "ı").is_empty());
+        assert!(spans("i", "İ").is_empty());
+        assert_eq!(spans("i\u{307}", "İ"), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn unicode_word_boundaries() {
+        assert_eq!(word_boundaries("can't stop"), vec![0, 5, 6, 10]);
+        assert_eq!(word_boundaries("中文"), vec![0, 3, 6]);
+    }
+}
@@ -0,0 +1,213 @@
+// 把命令行上的文件和目录展开成需要搜索的文件列表
+// 目录会被递归遍历，遍历时遵守每一层目录中的 .gitignore / .ignore 以及 --include/--exclude
+use crate::glob::{Glob, IgnoreFile};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// 每个目录下会读取的忽略文件，后面的文件优先级更高
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+impl Filter {
+    // --include 只作用于文件，--exclude 对文件和目录都生效
+    fn accepts(&self, relative: &str, is_dir: bool) -> bool {
+        if self.exclude.iter().any(|g| glob_matches(g, relative)) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(|g| glob_matches(g, relative))
+    }
+}
+
+fn glob_matches(glob: &Glob, relative: &str) -> bool {
+    if glob.has_separator() {
+        glob.is_match(relative)
+    } else {
+        glob.is_match(relative.rsplit('/').next().unwrap_or(relative))
+    }
+}
+
+#[derive(Debug)]
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl std::error::Error for WalkError {}
+
+// 命令行上直接给出的文件总是会被搜索，不受忽略规则和 --include/--exclude 影响
+pub fn walk(paths: &[String], filter: &Filter) -> Vec<Result<PathBuf, WalkError>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            let mut walker = Walker {
+                root: &path,
+                filter,
+                ignores: Vec::new(),
+                files: &mut files,
+            };
+            walker.visit(&path);
+        } else {
+            files.push(Ok(path));
+        }
+    }
+
+    files
+}
+
+struct Walker<'a> {
+    root: &'a Path,
+    filter: &'a Filter,
+    // 从外到内每一层目录的忽略规则
+    ignores: Vec<(PathBuf, IgnoreFile)>,
+    files: &'a mut Vec<Result<PathBuf, WalkError>>,
+}
+
+impl<'a> Walker<'a> {
+    fn visit(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.files.push(Err(WalkError {
+                    path: dir.to_path_buf(),
+                    error,
+                }));
+                return;
+            }
+        };
+
+        let mut ignore = IgnoreFile::default();
+        for name in IGNORE_FILES.iter() {
+            if let Some(file) = IgnoreFile::from_file(&dir.join(name)) {
+                ignore.extend(file);
+            }
+        }
+        self.ignores.push((dir.to_path_buf(), ignore));
+
+        // 按文件名排序，保证每次输出的顺序都一样
+        let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            if entry.file_name() == ".git" {
+                continue;
+            }
+
+            let path = entry.path();
+            // 不跟随指向目录的符号链接，避免循环；指向文件的符号链接按文件处理
+            let is_dir = match entry.file_type() {
+                Ok(t) => t.is_dir(),
+                Err(_) => continue,
+            };
+
+            if self.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if !self.filter.accepts(&relative_to(self.root, &path), is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                self.visit(&path);
+            } else if path.is_file() {
+                self.files.push(Ok(path));
+            }
+        }
+
+        self.ignores.pop();
+    }
+
+    // 由内向外查找，离文件最近的忽略文件中命中的规则说了算
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for (dir, ignore) in self.ignores.iter().rev() {
+            if let Some(ignored) = ignore.matched(&relative_to(dir, path), is_dir) {
+                return ignored;
+            }
+        }
+        false
+    }
+}
+
+// 把路径转换成相对于 base、用 / 分隔的字符串，方便和 glob 比较
+fn relative_to(base: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempdir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("minigrep-walk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn found(dir: &Path, filter: &Filter) -> Vec<String> {
+        walk(&[dir.to_string_lossy().into_owned()], filter)
+            .into_iter()
+            .map(|f| relative_to(dir, &f.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn honours_gitignore_and_globs() {
+        let dir = tempdir("ignore");
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        for file in &[
+            "src/lib.rs",
+            "src/nested/a.rs",
+            "src/nested/notes.txt",
+            "src/debug.log",
+            "target/out.rs",
+            ".git/HEAD",
+            "poem.txt",
+        ] {
+            fs::write(dir.join(file), "text").unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.join("src/nested/.gitignore"), "notes.txt\n").unwrap();
+
+        assert_eq!(
+            found(&dir, &Filter::default()),
+            vec![
+                ".gitignore",
+                "poem.txt",
+                "src/lib.rs",
+                "src/nested/.gitignore",
+                "src/nested/a.rs"
+            ]
+        );
+
+        let filter = Filter {
+            include: vec![Glob::new("*.rs").unwrap()],
+            exclude: vec![Glob::new("nested").unwrap()],
+        };
+        assert_eq!(found(&dir, &filter), vec!["src/lib.rs"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+// --watch：搜索一遍之后每隔一段时间检查文件有没有变化，变化了就再搜索
+// 只是轮询文件的大小和修改时间，不依赖操作系统的文件变化通知
+// 普通文本文件只搜索新追加的完整行，行号和偏移接着上次的位置算，相当于 tail -f | grep；
+// 最后一行还没写完（没有换行符）时等它写完再搜索
+// 文件变小或者大小不变但修改时间变了说明被改写了（比如日志轮转后重新创建），从头重新搜索
+// 压缩文件、tar 包、带 BOM 或者指定了 --encoding 的文件没法从中间开始解码，变化后整个重新搜索
+use crate::archive::Compression;
+use crate::printer::Printer;
+use crate::BINARY_PREFIX;
+use crate::{is_binary, search_error, search_file, search_stream, walk, with_filename};
+use crate::{Config, SearchError, STDIN_PATH};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+const BOMS: [&[u8]; 3] = [b"\xef\xbb\xbf", b"\xff\xfe", b"\xfe\xff"];
+
+pub(crate) fn watch(config: &Config) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), config, with_filename(config));
+    let mut watcher = Watcher::new(config);
+    loop {
+        watcher.poll(&mut printer)?;
+        printer.flush()?;
+        thread::sleep(config.watch_interval);
+    }
+}
+
+struct Watched {
+    len: u64,
+    modified: Option<SystemTime>,
+    // 只搜索追加内容的文件已经搜索过的字节数和行数，None 表示变化后整个文件重新搜索
+    tail: Option<(u64, usize)>,
+}
+
+struct Watcher<'a> {
+    config: &'a Config,
+    files: HashMap<PathBuf, Watched>,
+    // 已经报告过的错误，问题解决之前不再重复报告
+    failing: HashSet<String>,
+    first: bool,
+}
+
+impl<'a> Watcher<'a> {
+    fn new(config: &'a Config) -> Watcher<'a> {
+        Watcher {
+            config,
+            files: HashMap::new(),
+            failing: HashSet::new(),
+            first: true,
+        }
+    }
+
+    // 检查一遍所有文件，新出现的目录下的文件也会被搜索；返回这一轮选中的行数
+    fn poll<W: Write>(&mut self, printer: &mut Printer<W>) -> io::Result<usize> {
+        let mut seen = HashSet::new();
+        let mut failing = HashSet::new();
+        let mut count = 0;
+
+        for target in walk::walk(&self.config.paths, &self.config.filter) {
+            let result = match target {
+                // 标准输入本来就是边读边搜，只在第一轮读一次
+                Ok(path) if path == Path::new(STDIN_PATH) => {
+                    if !self.first {
+                        continue;
+                    }
+                    search_file(self.config, &path, printer).map_err(|e| search_error(&path, e))
+                }
+                Ok(path) => {
+                    let result = self
+                        .check(&path, printer)
+                        .map_err(|e| search_error(&path, e));
+                    seen.insert(path);
+                    result
+                }
+                Err(e) => Err(SearchError::File(e.to_string())),
+            };
+            match result {
+                Ok(n) => count += n,
+                Err(SearchError::File(message)) => {
+                    if !self.failing.contains(&message) {
+                        eprintln!("minigrep: {}", message);
+                    }
+                    failing.insert(message);
+                }
+                Err(SearchError::Output(e)) => return Err(e),
+            }
+        }
+
+        // 被删除的文件不再跟踪，重新出现时当作新文件
+        self.files.retain(|path, _| seen.contains(path));
+        self.failing = failing;
+        self.first = false;
+        Ok(count)
+    }
+
+    fn check<W: Write>(
+        &mut self,
+        path: &Path,
+        printer: &mut Printer<W>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let metadata = fs::metadata(path)?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+
+        let tail = match self.files.get(path) {
+            Some(watched) if watched.len == len && watched.modified == modified => return Ok(0),
+            // 变大了就是追加，其他变化都当作改写
+            Some(watched) if len > watched.len && watched.tail.is_some() => watched.tail,
+            _ if self.tailable(path)? => Some((0, 0)),
+            _ => None,
+        };
+
+        let (count, tail) = match tail {
+            Some((offset, lines)) => {
+                let (count, offset, lines) =
+                    self.search_appended(path, offset, lines, len, printer)?;
+                (count, Some((offset, lines)))
+            }
+            None => (search_file(self.config, path, printer)?, None),
+        };
+        self.files.insert(
+            path.to_path_buf(),
+            Watched {
+                len,
+                modified,
+                tail,
+            },
+        );
+        Ok(count)
+    }
+
+    // 能不能只搜索追加的内容：没有压缩、不是二进制（tar 包里有 NUL）、不需要解码
+    fn tailable(&self, path: &Path) -> io::Result<bool> {
+        if self.config.encoding != crate::encoding::Encoding::Auto {
+            return Ok(false);
+        }
+        let mut head = Vec::new();
+        File::open(path)?
+            .take(BINARY_PREFIX as u64)
+            .read_to_end(&mut head)?;
+        Ok(Compression::detect(&head) == Compression::None
+            && !is_binary(&head)
+            && !BOMS.iter().any(|bom| head.starts_with(bom)))
+    }
+
+    // 从 offset 开始搜索到 len 为止的完整行，返回选中的行数和新的位置
+    fn search_appended<W: Write>(
+        &self,
+        path: &Path,
+        offset: u64,
+        lines: usize,
+        len: u64,
+        printer: &mut Printer<W>,
+    ) -> Result<(usize, u64, usize), Box<dyn Error>> {
+        let mut file = File::open(path)?;
+        let end = match last_line_end(&mut file, offset, len)? {
+            Some(end) => end,
+            None => return Ok((0, offset, lines)),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = CountLines {
+            inner: BufReader::new(file.take(end - offset)),
+            lines: 0,
+        };
+        let count = search_stream(
+            self.config,
+            path,
+            &mut reader,
+            (lines, offset as usize),
+            printer,
+        )?;
+        // -m 或者二进制内容会让搜索提前结束，剩下的行同样要计入行号
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok((count, end, lines + reader.lines))
+    }
+}
+
+// offset 到 len 之间最后一个换行符之后的位置，没有换行符时返回 None
+// 从末尾一块一块往回找，读取的只是最后那一行还没写完的部分
+fn last_line_end(file: &mut File, offset: u64, len: u64) -> io::Result<Option<u64>> {
+    let mut block = [0; 8192];
+    let mut end = len;
+    while end > offset {
+        let start = end.saturating_sub(block.len() as u64).max(offset);
+        let block = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        if let Some(i) = memchr::memrchr(b'\n', block) {
+            return Ok(Some(start + i as u64 + 1));
+        }
+        end = start;
+    }
+    Ok(None)
+}
+
+// 统计读过的换行符个数，也就是读过的完整行数
+struct CountLines<R> {
+    inner: R,
+    lines: usize,
+}
+
+impl<R: BufRead> Read for CountLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountLines<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // 没有消耗的内容还在缓冲区里，这里的 fill_buf 不会真的读取
+        if let Ok(available) = self.inner.fill_buf() {
+            let consumed = &available[..amt.min(available.len())];
+            self.lines += memchr::memchr_iter(b'\n', consumed).count();
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        let mut args = vec![String::from("minigrep")];
+        args.extend(list.iter().map(|s| s.to_string()));
+        args.into_iter()
+    }
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn searches_only_appended_lines() {
+        let dir = env::temp_dir().join(format!("minigrep-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "a x\nb\n").unwrap();
+        let path = log.to_str().unwrap();
+
+        let config = Config::new(args(&["-n", "--watch", "x", path])).unwrap();
+        let mut watcher = Watcher::new(&config);
+        let mut poll = |expected: &str| {
+            let mut printer = Printer::new(Vec::new(), &config, false);
+            watcher.poll(&mut printer).unwrap();
+            assert_eq!(String::from_utf8(printer.into_inner()).unwrap(), expected);
+        };
+
+        poll("1:a x\n");
+        poll("");
+        // 没写完的一行等写完再搜索
+        append(&log, "c x\nd");
+        poll("3:c x\n");
+        append(&log, "x\n");
+        poll("4:dx\n");
+        // 变小了说明被改写，从头搜索
+        fs::write(&log, "x\n").unwrap();
+        poll("1:x\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn line_numbers_survive_early_stop() {
+        let dir = env::temp_dir().join(format!("minigrep-watch-m-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "").unwrap();
+        let path = log.to_str().unwrap();
+
+        // -m1 在每次追加的内容中找到一行就停止，没读到的行也要计入行号
+        let config = Config::new(args(&["-n", "-m1", "--watch", "x", path])).unwrap();
+        let mut watcher = Watcher::new(&config);
+        let mut poll = |expected: &str| {
+            let mut printer = Printer::new(Vec::new(), &config, false);
+            watcher.poll(&mut printer).unwrap();
+            assert_eq!(String::from_utf8(printer.into_inner()).unwrap(), expected);
+        };
+
+        poll("");
+        append(&log, &"x\n".repeat(5000));
+        poll("1:x\n");
+        append(&log, "a\nx\n");
+        poll("5002:x\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
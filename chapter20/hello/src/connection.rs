@@ -0,0 +1,561 @@
+// 一个 TCP 连接上的请求循环
+// 持久连接：HTTP/1.1 默认保持连接，除非请求带了 Connection: close；HTTP/1.0 只有带了 Connection: keep-alive 才保持
+// 流水线：客户端可以不等响应就连续发送多个请求，它们按顺序读取、按顺序响应，多读到的数据留在 BufReader 里给下一个请求
+// 空闲超过 idle_timeout 或者处理了 max_requests 个请求之后关闭连接，避免少数客户端一直占着 worker
+// 每个请求从第一个字节开始最多读 request_timeout，一个字节一个字节慢慢发送的客户端（slowloris）也占不住 worker
+// 线程池里有连接在排队时，处理过请求、正在空闲等待的连接主动关闭，把 worker 让给排队的连接；
+// 刚建立、还没发来请求的连接不算空闲，否则排队的连接一轮到就会被关掉
+// 开始停机之后，写完当前的响应就关闭连接；停机之前已经开始发送的请求照常读完、处理
+use crate::request::{ParseError, Request};
+use crate::response::Response;
+use crate::shutdown::Tracked;
+use crate::Backlog;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+// 空闲等待时每隔多久检查一次线程池中有没有排队的连接
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    // 两个请求之间最多等待多久
+    pub idle_timeout: Duration,
+    // 从请求的第一个字节开始，读完请求行、header 和 body 最多用多久
+    pub request_timeout: Duration,
+    // 一个连接上最多处理多少个请求
+    pub max_requests: usize,
+    // 连接所在线程池的排队任务数，有任务在排队时不保持空闲连接
+    pub backlog: Option<Backlog>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            idle_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            max_requests: 100,
+            backlog: None,
+        }
+    }
+}
+
+/// `serve` 的读取端
+///
+/// 每个请求开始读之前 `serve` 会调用 `next_request`，实现者可以据此重新计算读取期限
+pub trait Source: BufRead {
+    fn next_request(&mut self) {}
+}
+
+impl Source for &[u8] {}
+
+impl Source for BufReader<Timed<'_>> {
+    fn next_request(&mut self) {
+        // 流水线中下一个请求已经有数据读进来了，它的期限从现在开始算
+        let started = !self.buffer().is_empty();
+        self.get_mut().next_request(started);
+    }
+}
+
+/// 按 `Options` 中的期限读取 TCP 连接
+#[derive(Debug)]
+pub struct Timed<'a> {
+    stream: &'a TcpStream,
+    options: &'a Options,
+    tracked: Option<&'a Tracked>,
+    // 当前请求的读取期限，None 表示还在等下一个请求的第一个字节
+    deadline: Option<Instant>,
+    // 已经开始读的请求数
+    requests: usize,
+}
+
+impl<'a> Timed<'a> {
+    pub fn new(
+        stream: &'a TcpStream,
+        options: &'a Options,
+        tracked: Option<&'a Tracked>,
+    ) -> Timed<'a> {
+        Timed {
+            stream,
+            options,
+            tracked,
+            deadline: None,
+            requests: 0,
+        }
+    }
+
+    // 返回 false 表示已经开始停机，这个空闲连接不应该再读新的请求
+    fn next_request(&mut self, started: bool) -> bool {
+        if !started {
+            self.deadline = None;
+            return true;
+        }
+        if !self.tracked.is_none_or(Tracked::receive) {
+            return false;
+        }
+        self.requests += 1;
+        self.deadline = Some(Instant::now() + self.options.request_timeout);
+        true
+    }
+
+    // 等待下一个请求的第一个字节：分成小段等待，
+    // 已经处理过请求的连接在线程池里有连接排队时当作连接已关闭
+    fn read_idle(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let idle_deadline = Instant::now() + self.options.idle_timeout;
+        let backlog = match &self.options.backlog {
+            Some(backlog) if self.requests > 0 => Some(backlog),
+            _ => None,
+        };
+        loop {
+            if backlog.is_some_and(|b| !b.is_empty()) {
+                return Ok(0);
+            }
+            let remaining = idle_deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let wait = if backlog.is_some() {
+                remaining.min(IDLE_POLL)
+            } else {
+                remaining
+            };
+            self.stream.set_read_timeout(Some(wait))?;
+            // 先 peek 不取走数据，在停机的锁下把连接标记为正在接收请求之后再读，
+            // 这样停机不会在读到第一个字节和标记之间关闭读端
+            match self.stream.peek(buf) {
+                Ok(0) => return Ok(0),
+                Ok(_) => {
+                    if !self.next_request(true) {
+                        return Ok(0);
+                    }
+                    return self.read(buf);
+                }
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Read for Timed<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return self.read_idle(buf),
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+/// 处理一个 TCP 连接上的所有请求，直到连接关闭、空闲超时或者达到请求数上限
+///
+/// tracked 是 `Shutdown::track` 返回的跟踪记录，用于优雅停机
+///
+/// 出错时只打印到 stderr，返回处理过的请求数
+pub fn handle<F>(
+    stream: TcpStream,
+    options: &Options,
+    tracked: Option<&Tracked>,
+    handler: F,
+) -> usize
+where
+    F: Fn(&Request) -> Response,
+{
+    // 客户端一直不读响应时，写入同样不能无限期地占着 worker
+    if let Err(e) = stream.set_write_timeout(Some(options.request_timeout)) {
+        eprintln!("Failed to set write timeout: {}", e);
+        return 0;
+    }
+    let mut reader = BufReader::new(Timed::new(&stream, options, tracked));
+    let mut writer = &stream;
+    let mut served = 0;
+    if let Err(e) = serve(
+        &mut reader,
+        &mut writer,
+        options,
+        tracked,
+        &mut served,
+        handler,
+    ) {
+        eprintln!("Connection error after {} request(s): {}", served, e);
+    }
+    served
+}
+
+/// 从 reader 中依次读取请求，交给 handler，把响应按顺序写到 writer
+///
+/// served 记录处理过的请求数，出错返回时也是准确的
+pub fn serve<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &Options,
+    tracked: Option<&Tracked>,
+    served: &mut usize,
+    handler: F,
+) -> io::Result<()>
+where
+    R: Source,
+    W: Write,
+    F: Fn(&Request) -> Response,
+{
+    loop {
+        reader.next_request();
+        let request = match Request::read(reader) {
+            Ok(Some(request)) => request,
+            // 客户端关闭了连接，或者在超时之前没有发来新的请求
+            Ok(None) => return Ok(()),
+            Err(ParseError::Io(e)) if is_timeout(&e) => return Ok(()),
+            Err(ParseError::Io(e)) => return Err(e),
+            // 请求格式错误之后无法确定下一个请求从哪里开始，响应之后关闭连接
+            Err(e) => return e.response().write_to(writer),
+        };
+        *served += 1;
+        if let Some(tracked) = tracked {
+            tracked.begin(&request);
+        }
+
+        let mut response = handler(&request);
+        let keep_alive = keep_alive(&request)
+            && *served < options.max_requests
+            && !tracked.is_some_and(Tracked::is_stopping)
+            && !has_token(response.get_header("Connection"), "close");
+        if keep_alive {
+            if request.version == "HTTP/1.0" {
+                response.set_header("Connection", "keep-alive");
+            }
+            let keep = format!(
+                "timeout={}, max={}",
+                options.idle_timeout.as_secs(),
+                options.max_requests - *served
+            );
+            response.set_header("Keep-Alive", &keep);
+        } else {
+            response.set_header("Connection", "close");
+        }
+
+        if request.method == "HEAD" {
+            response.write_head(writer)?;
+            writer.flush()?;
+        } else {
+            response.write_to(writer)?;
+        }
+        // 处理请求期间开始了停机的话，这个响应没来得及带上 Connection: close，但连接同样要关闭
+        let stopping = tracked.is_some_and(Tracked::finish);
+        if !keep_alive || stopping {
+            return Ok(());
+        }
+    }
+}
+
+fn keep_alive(request: &Request) -> bool {
+    let connection = request.header("Connection");
+    if request.version == "HTTP/1.0" {
+        has_token(connection, "keep-alive")
+    } else {
+        !has_token(connection, "close")
+    }
+}
+
+// Connection 的值是逗号分隔、不区分大小写的列表
+fn has_token(value: Option<&str>, token: &str) -> bool {
+    value.is_some_and(|value| {
+        value
+            .split(',')
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+// 设置了读取超时的 socket 在超时时返回 WouldBlock（Unix）或 TimedOut（Windows）
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str, max_requests: usize) -> (String, usize) {
+        let options = Options {
+            max_requests,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut served = 0;
+        serve(
+            &mut input.as_bytes(),
+            &mut output,
+            &options,
+            None,
+            &mut served,
+            |request| Response::new(200).body(request.path.clone()),
+        )
+        .unwrap();
+        (String::from_utf8(output).unwrap(), served)
+    }
+
+    // 每个响应的 Connection header 和 body
+    fn responses(output: &str) -> Vec<(Option<&str>, &str)> {
+        output
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|response| {
+                let (head, body) = response.split_once("\r\n\r\n").unwrap();
+                let connection = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Connection: "));
+                (connection, body)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pipelined_requests_answered_in_order() {
+        let input = "GET /a HTTP/1.1\r\nHost: h\r\n\r\n\
+                     POST /b HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\n\r\nxyz\
+                     GET /c HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n\
+                     GET /never HTTP/1.1\r\nHost: h\r\n\r\n";
+        let (output, served) = run(input, 100);
+        assert_eq!(served, 3);
+        assert_eq!(
+            responses(&output),
+            vec![(None, "/a"), (None, "/b"), (Some("close"), "/c")]
+        );
+        assert!(output.contains("Keep-Alive: timeout=5, max=99\r\n"));
+    }
+
+    #[test]
+    fn closes_at_request_limit_and_for_http10() {
+        let input = "GET /1 HTTP/1.1\r\nHost: h\r\n\r\n".repeat(3);
+        let (output, served) = run(&input, 2);
+        assert_eq!(served, 2);
+        assert_eq!(responses(&output)[1], (Some("close"), "/1"));
+
+        let (output, served) = run("GET /old HTTP/1.0\r\n\r\nGET /old HTTP/1.0\r\n\r\n", 100);
+        assert_eq!(served, 1);
+        assert_eq!(responses(&output), vec![(Some("close"), "/old")]);
+
+        let input = "GET /1 HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\nGET /2 HTTP/1.0\r\n\r\n";
+        let (output, served) = run(input, 100);
+        assert_eq!(served, 2);
+        assert_eq!(
+            responses(&output),
+            vec![(Some("keep-alive"), "/1"), (Some("close"), "/2")]
+        );
+    }
+
+    #[test]
+    fn bad_request_ends_connection() {
+        let (output, served) = run("GET /a HTTP/1.1\r\nHost: h\r\n\r\nnonsense\r\n\r\n", 100);
+        assert_eq!(served, 1);
+        assert!(output.ends_with("malformed request line: \"nonsense\"\n"));
+        assert!(output.contains("HTTP/1.1 400 Bad Request"));
+    }
+
+    // 发送一个保持连接的请求，读完响应但不关闭连接
+    fn keep_alive_request(addr: std::net::SocketAddr) -> (TcpStream, String) {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client
+            .write_all(b"GET /ok HTTP/1.1\r\nHost: h\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        let mut buf = [0; 256];
+        while !response.ends_with(b"/ok") {
+            let n = client.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed before the response");
+            response.extend_from_slice(&buf[..n]);
+        }
+        (client, String::from_utf8(response).unwrap())
+    }
+
+    #[test]
+    fn idle_connections_yield_workers_to_queued_ones() {
+        use crate::ThreadPool;
+        use std::net::TcpListener;
+
+        const WORKERS: usize = 2;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let pool = ThreadPool::silent(WORKERS);
+            let options = Options {
+                idle_timeout: Duration::from_secs(30),
+                backlog: Some(pool.backlog()),
+                ..Options::default()
+            };
+            for stream in listener.incoming().take(WORKERS + 1) {
+                let options = options.clone();
+                pool.execute(move || {
+                    handle(stream.unwrap(), &options, None, |request| {
+                        Response::new(200).body(request.path.clone())
+                    });
+                });
+            }
+        });
+
+        // 前 WORKERS 个连接保持空闲，占满所有 worker
+        let started = Instant::now();
+        let clients: Vec<TcpStream> = (0..=WORKERS)
+            .map(|_| {
+                let (client, response) = keep_alive_request(addr);
+                assert!(response.starts_with("HTTP/1.1 200 OK"));
+                client
+            })
+            .collect();
+        // 不必等到 30 秒的空闲超时
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // 被让出 worker 的是空闲最久的连接之一，它读到的是连接关闭
+        let closed = clients
+            .iter()
+            .filter(|client| {
+                client
+                    .set_read_timeout(Some(Duration::from_millis(200)))
+                    .unwrap();
+                matches!((&**client).read(&mut [0; 16]), Ok(0))
+            })
+            .count();
+        assert!(closed >= 1);
+        drop(clients);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn new_connections_are_served_while_others_queue() {
+        use std::net::TcpListener;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut client = TcpStream::connect(addr).unwrap();
+            // 晚于一次空闲检查再发请求
+            std::thread::sleep(IDLE_POLL * 2);
+            client
+                .write_all(b"GET /ok HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let options = Options {
+            backlog: Some(Backlog(Arc::new(AtomicUsize::new(1)))),
+            ..Options::default()
+        };
+        let served = handle(stream, &options, None, |request| {
+            Response::new(200).body(request.path.clone())
+        });
+        assert_eq!(served, 1);
+        let response = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("/ok"));
+    }
+
+    #[test]
+    fn requests_started_before_shutdown_complete() {
+        use crate::Shutdown;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let request = b"GET /ok HTTP/1.1\r\nHost: h\r\n\r\n";
+        // 一个连接已经发完请求但还在排队，没有处理过请求
+        let mut queued = TcpStream::connect(addr).unwrap();
+        queued.write_all(request).unwrap();
+        let (queued_server, _) = listener.accept().unwrap();
+        let queued_tracked = shutdown.track(&queued_server).unwrap();
+
+        let server = {
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let tracked = shutdown.track(&stream).unwrap();
+                handle(stream, &Options::default(), Some(&tracked), |request| {
+                    Response::new(200).body(request.path.clone())
+                })
+            })
+        };
+        // 另一个连接处理完一个请求之后，下一个请求只发了一半
+        let (mut partial, _) = keep_alive_request(addr);
+        partial.write_all(&request[..20]).unwrap();
+        // 等服务端读到前半个请求
+        std::thread::sleep(Duration::from_millis(100));
+        shutdown.trigger();
+        partial.write_all(&request[20..]).unwrap();
+        let served = handle(
+            queued_server,
+            &Options::default(),
+            Some(&queued_tracked),
+            |request| Response::new(200).body(request.path.clone()),
+        );
+        // 跟踪记录里有 socket 的副本，去掉之后连接才真正关闭
+        drop(queued_tracked);
+        assert_eq!((served, server.join().unwrap()), (1, 2));
+
+        for mut client in [partial, queued] {
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.contains("Connection: close\r\n"));
+            assert!(response.ends_with("/ok"));
+        }
+    }
+
+    #[test]
+    fn slow_requests_hit_the_deadline() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut client = TcpStream::connect(addr).unwrap();
+            // 每个字节都在空闲超时之内到达，但整个请求永远发不完
+            for b in b"GET / HTTP/1.1\r\nHost: h\r\nX-Slow: "
+                .iter()
+                .cycle()
+                .take(40)
+            {
+                if client.write_all(&[*b]).is_err() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let options = Options {
+            idle_timeout: Duration::from_millis(100),
+            request_timeout: Duration::from_millis(200),
+            ..Options::default()
+        };
+        let started = Instant::now();
+        let served = handle(stream, &options, None, |_| Response::new(200));
+        assert_eq!(served, 0);
+        assert!(started.elapsed() < Duration::from_millis(600));
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn head_has_length_but_no_body() {
+        let (output, _) = run(
+            "HEAD /abc HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n",
+            100,
+        );
+        assert!(output.contains("Content-Length: 4\r\n"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+}
@@ -1,10 +1,42 @@
-use crate::Message::NewJob;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
+pub mod connection;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod shutdown;
+pub mod static_files;
+
+pub use request::{ParseError, Request};
+pub use response::Response;
+pub use router::{Params, Router};
+pub use shutdown::Shutdown;
+pub use static_files::StaticFiles;
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
+    // 是否向 stdout 打印 worker 的运行日志
+    log: bool,
+    backlog: Backlog,
+}
+
+/// 已经提交但还没有 worker 开始执行的任务数
+///
+/// 不为 0 说明所有 worker 都在忙，长时间占着 worker 的任务（例如空闲的持久连接）应该尽快让出来
+#[derive(Debug, Clone, Default)]
+pub struct Backlog(Arc<AtomicUsize>);
+
+impl Backlog {
+    pub fn len(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 struct Worker {
@@ -24,7 +56,7 @@ impl<F: FnOnce()> FnBox for F {
     }
 }
 
-type Job = Box<FnBox + Send + 'static>;
+type Job = Box<dyn FnBox + Send + 'static>;
 
 enum Message {
     NewJob(Job),
@@ -32,7 +64,12 @@ enum Message {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+    fn new(
+        id: usize,
+        receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
+        backlog: Backlog,
+        log: bool,
+    ) -> Worker {
         // 创建一个线程的时候就需要给定对应的闭包，这里用空闭包填充
         let thread = thread::spawn(move || loop {
             // 在 receiver 上调用 lock 来获取互斥器
@@ -48,7 +85,10 @@ impl Worker {
             let message = receiver.lock().unwrap().recv().expect("获取消息锁失败");
             match message {
                 Message::NewJob(job) => {
-                    println!("Worker {} got a job, executing.", id);
+                    backlog.0.fetch_sub(1, Ordering::SeqCst);
+                    if log {
+                        println!("Worker {} got a job, executing.", id);
+                    }
                     // cannot move a value of type dyn std::ops::FnOnce() + std::marker::Send: the size of
                     // dyn std::ops::FnOnce() + std::marker::Send cannot be statically determined
                     // 此处为了调用存储在 Box<T> 中的 T，即 FnOnce 闭包，该闭包需要能将自己移出 Box<T>，
@@ -61,7 +101,9 @@ impl Worker {
                     job.call_box();
                 }
                 Message::Terminate => {
-                    println!("Worker {} was told to terminate.", id);
+                    if log {
+                        println!("Worker {} was told to terminate.", id);
+                    }
 
                     break;
                 }
@@ -83,18 +125,45 @@ impl ThreadPool {
     ///
     /// `new` 函数在 size = 0 时会 panic
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::build(size, true)
+    }
+
+    /// 创建不打印日志的线程池
+    ///
+    /// 和 `new` 一样，只是 worker 和停机过程都不会向 stdout 打印日志，
+    /// 适合 stdout 本身就是输出结果的命令行程序
+    ///
+    /// # Panics
+    ///
+    /// `silent` 函数在 size = 0 时会 panic
+    pub fn silent(size: usize) -> ThreadPool {
+        ThreadPool::build(size, false)
+    }
+
+    fn build(size: usize, log: bool) -> ThreadPool {
         assert!(size > 0);
 
         let (sender, receiver) = mpsc::channel();
 
         let receiver = Arc::new(Mutex::new(receiver));
 
+        let backlog = Backlog::default();
         let mut workers = Vec::with_capacity(size);
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), backlog.clone(), log));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers,
+            sender,
+            log,
+            backlog,
+        }
+    }
+
+    /// 返回可以在 worker 中查询排队任务数的句柄
+    pub fn backlog(&self) -> Backlog {
+        self.backlog.clone()
     }
 
     // FnOnce 仍然需要后面的 ()，因为这里的 FnOnce 代表一个没有参数也没有返回值的闭包
@@ -110,6 +179,7 @@ impl ThreadPool {
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
+        self.backlog.0.fetch_add(1, Ordering::SeqCst);
         self.sender
             .send(Message::NewJob(job))
             .expect("发送消息失败");
@@ -119,7 +189,9 @@ impl ThreadPool {
 // 优雅停机：对线程池实现 Drop trait 并 join 各个线程等待其结束
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        println!("Sending terminate message to all workers down worker");
+        if self.log {
+            println!("Sending terminate message to all workers down worker");
+        }
 
         // 这里之所以使用2个for循环将 Terminate 消息和等待worker执行完分开，
         // 是为了防止 Terminate 消息被其他线程接受后再调用本线程的 join 时会因为得不到消息锁而一直等待，
@@ -130,17 +202,21 @@ impl Drop for ThreadPool {
                 .expect("发送终止消息失败");
         }
 
-        println!("Shutting down all workers.");
+        if self.log {
+            println!("Shutting down all workers.");
+        }
 
         // 这里使用了 &mut 是因为 self 本身是一个可变引用而且也需要能够修改 worker
         for worker in &mut self.workers {
             // Option<T>.take() 会将T取出而留下None，所以take()后面不能再链式调用
             if let Some(thread) = worker.thread.take() {
-                println!("Shutting down worker {}", worker.id);
+                if self.log {
+                    println!("Shutting down worker {}", worker.id);
+                }
 
                 thread
                     .join()
-                    .expect(format!("等待线程 {} 执行完毕失败", worker.id).as_str());
+                    .unwrap_or_else(|_| panic!("等待线程 {} 执行完毕失败", worker.id));
             }
         }
     }
@@ -155,4 +231,19 @@ mod tests {
     fn negative_size_new() {
         ThreadPool::new(0);
     }
+
+    #[test]
+    fn silent_pool_runs_jobs() {
+        let (tx, rx) = mpsc::channel();
+        let pool = ThreadPool::silent(2);
+        for i in 0..4 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        drop(pool);
+
+        let mut results: Vec<i32> = rx.try_iter().collect();
+        results.sort();
+        assert_eq!(results, vec![0, 1, 2, 3]);
+    }
 }
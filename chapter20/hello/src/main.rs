@@ -1,12 +1,36 @@
-use hello::ThreadPool;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use hello::connection::{self, Options};
+use hello::{Response, Router, Shutdown, StaticFiles, ThreadPool};
+use std::net::TcpListener;
+use std::sync::Arc;
 use std::time::Duration;
-use std::{fs, thread};
+use std::{env, fs, process, thread};
+
+// 停机时等待正在处理的请求的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 fn main() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
     let pool = ThreadPool::new(4);
+    // 所有 worker 都在忙时，空闲的持久连接会让出 worker
+    let options = Options {
+        backlog: Some(pool.backlog()),
+        ..Options::default()
+    };
+    let shutdown = Shutdown::new();
+    shutdown.wake_on(&listener).unwrap();
+    // 所有 worker 共享同一个 Router
+    let router = Arc::new(routes(&shutdown));
+
+    // Ctrl-C（SIGINT）或者 SIGTERM 开始停机，停机期间再收到一次就直接退出
+    let signal = shutdown.clone();
+    ctrlc::set_handler(move || {
+        if signal.is_stopping() {
+            eprintln!("Forced exit.");
+            process::exit(130);
+        }
+        signal.trigger();
+    })
+    .expect("设置信号处理函数失败");
 
     //    // incoming 返回 TcpStream 的迭代器，stream 代表一个客户端和服务端之间打开的 connection
     //    // connection 代表客户端连接服务端、服务端生成响应以及服务端关系连接的全部请求/响应过程
@@ -16,41 +40,102 @@ fn main() {
     //        pool.execute(|| handle_connection(stream));
     //    }
 
-    // show case for graceful shutting down after 2 requests
-    for stream in listener.incoming().take(2) {
-        let stream = stream.unwrap();
+    // 一直 accept 到开始停机，停机时 Shutdown 会连接一次 listener 把循环唤醒
+    for stream in listener.incoming() {
+        if shutdown.is_stopping() {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        // 连接在 accept 时就开始跟踪，还在队列里等 worker 的连接也算在内
+        let tracked = match shutdown.track(&stream) {
+            Ok(tracked) => tracked,
+            Err(e) => {
+                eprintln!("Failed to track connection: {}", e);
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+        let options = options.clone();
+
+        // 持久连接会一直占用一个 worker，直到空闲超时、达到请求数上限、有连接在排队或者开始停机
+        pool.execute(move || {
+            connection::handle(stream, &options, Some(&tracked), |request| {
+                router.handle(request)
+            });
+        });
+    }
 
-        pool.execute(|| handle_connection(stream));
+    // 关闭监听的 socket，新的连接会被直接拒绝
+    drop(listener);
+    println!(
+        "Shutting down, waiting up to {:?} for {} connection(s).",
+        SHUTDOWN_TIMEOUT,
+        shutdown.open()
+    );
+    let dropped = shutdown.wait(SHUTDOWN_TIMEOUT);
+    if dropped > 0 {
+        // 被强制关闭的连接所在的 worker 可能还卡在 handler 里，不再等待它们
+        eprintln!(
+            "Dropped {} connection(s), exiting without joining workers.",
+            dropped
+        );
+        process::exit(1);
     }
 
+    // ThreadPool 的 Drop 会通知并等待所有 worker 结束
+    drop(pool);
     println!("Shutting down.")
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
-
-    stream.read(&mut buffer).unwrap();
-    // println!("Request: {}", String::from_utf8_lossy(&buffer[..]));
-
-    // b"" 字节字符串语法将其转换为字节字符串 &str -> &[u8,]
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html")
-    };
-
-    let contents = fs::read_to_string(filename).unwrap();
-
-    let response = format!("{}{}", status_line, contents);
+fn routes(shutdown: &Shutdown) -> Router {
+    // 第一个命令行参数是静态文件的根目录，默认是当前目录
+    let root = env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    let files = StaticFiles::new(root).listing(true);
+
+    Router::new()
+        .get("/", |_, _| page(200, "hello.html"))
+        .get("/sleep", |_, _| {
+            thread::sleep(Duration::from_secs(5));
+            page(200, "hello.html")
+        })
+        .get("/users/:id", |_, params| {
+            Response::new(200)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!("Hello, user {}!\n", params.get("id").unwrap_or("")))
+        })
+        .get("/static/*path", move |request, params| {
+            files.serve(request, params.get("path").unwrap_or(""))
+        })
+        // 服务只监听 127.0.0.1，本机才能调用这个接口
+        .post("/admin/shutdown", {
+            let shutdown = shutdown.clone();
+            move |_, _| {
+                shutdown.trigger();
+                Response::new(202)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body("Shutting down\n")
+            }
+        })
+        .not_found(|_, _| page(404, "404.html"))
+}
 
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+// 以 HTML 文件作为 body 的响应
+fn page(status: u16, filename: &str) -> Response {
+    match fs::read(filename) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", filename, e);
+            Response::new(500)
+        }
+    }
 }
 
 // HTTP 是一个基于文本的协议
@@ -0,0 +1,538 @@
+// HTTP/1.1 请求的解析
+// 从 BufRead 中逐行读取，所以请求可以比任何固定大小的缓冲区都大，也可以分成多次 TCP 读取到达
+// 格式错误的请求返回 ParseError，由调用方转换成对应的 4xx/5xx 响应，而不是 panic
+use crate::response::Response;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+// 请求行和每个 header 行的最大长度，整个 header 部分的最大长度，以及 body 的最大长度
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64 * 1024;
+const MAX_BODY: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    // 请求行中原样的请求目标，例如 /search?q=a%20b
+    pub target: String,
+    // 请求目标中没有解码的路径，例如 /files/a%2Fb，路由按它的 / 分段，%2F 不会被当成分隔符
+    pub raw_path: String,
+    // 百分号解码后的路径，例如 /files/a/b
+    pub path: String,
+    // 解码后的查询参数，按出现顺序排列，+ 解码为空格
+    pub query: Vec<(String, String)>,
+    pub version: String,
+    // header 按出现顺序保存，名字保留原来的大小写
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    // 请求没有读完连接就关闭了
+    UnexpectedEof,
+    BadRequestLine(String),
+    BadTarget(String),
+    UnsupportedVersion(String),
+    BadHeader(String),
+    MissingHost,
+    BadContentLength(String),
+    BadChunk(String),
+    UnsupportedEncoding(String),
+    // 多个 Transfer-Encoding，或者同时有 Transfer-Encoding 和 Content-Length
+    AmbiguousLength(String),
+    UriTooLong,
+    LineTooLong,
+    HeadersTooLarge,
+    BodyTooLarge,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "{}", e),
+            ParseError::UnexpectedEof => {
+                write!(f, "connection closed in the middle of the request")
+            }
+            ParseError::BadRequestLine(line) => write!(f, "malformed request line: {:?}", line),
+            ParseError::BadTarget(target) => write!(f, "malformed request target: {:?}", target),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported HTTP version: {:?}", version)
+            }
+            ParseError::BadHeader(line) => write!(f, "malformed header: {:?}", line),
+            ParseError::MissingHost => write!(f, "HTTP/1.1 requests must have a Host header"),
+            ParseError::BadContentLength(value) => {
+                write!(f, "invalid Content-Length: {:?}", value)
+            }
+            ParseError::BadChunk(reason) => write!(f, "malformed chunked body: {}", reason),
+            ParseError::UnsupportedEncoding(coding) => {
+                write!(f, "unsupported Transfer-Encoding: {:?}", coding)
+            }
+            ParseError::AmbiguousLength(headers) => {
+                write!(f, "ambiguous message length: {}", headers)
+            }
+            ParseError::UriTooLong => write!(f, "request line longer than {} bytes", MAX_LINE),
+            ParseError::LineTooLong => write!(f, "header line longer than {} bytes", MAX_LINE),
+            ParseError::HeadersTooLarge => write!(f, "headers larger than {} bytes", MAX_HEADERS),
+            ParseError::BodyTooLarge => write!(f, "body larger than {} bytes", MAX_BODY),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+impl ParseError {
+    /// 错误对应的响应状态码
+    pub fn status(&self) -> u16 {
+        match self {
+            ParseError::UnsupportedVersion(_) => 505,
+            ParseError::UnsupportedEncoding(_) => 501,
+            ParseError::UriTooLong => 414,
+            ParseError::LineTooLong | ParseError::HeadersTooLarge => 431,
+            ParseError::BodyTooLarge => 413,
+            _ => 400,
+        }
+    }
+
+    /// 返回给客户端的错误响应
+    ///
+    /// body 是纯文本的错误说明，响应之后连接会被关闭
+    pub fn response(&self) -> Response {
+        Response::new(self.status())
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("Connection", "close")
+            .body(format!("{}\n", self))
+    }
+}
+
+impl Request {
+    /// 从 reader 中读取并解析一个完整的请求，包括 body
+    ///
+    /// 连接在请求开始之前就正常关闭时返回 `Ok(None)`
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Option<Request>, ParseError> {
+        // 和 RFC 7230 建议的一样，忽略请求行前面的空行
+        let line = loop {
+            // 请求行太长基本上都是因为 URI 太长（RFC 9112 第 3 节）
+            let line = match read_line(reader) {
+                Err(ParseError::LineTooLong) => return Err(ParseError::UriTooLong),
+                line => line?,
+            };
+            match line {
+                None => return Ok(None),
+                Some(line) if line.is_empty() => continue,
+                Some(line) => break line,
+            }
+        };
+
+        let mut parts = line.split(' ');
+        let (method, target, version) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(m), Some(t), Some(v), None)
+                    if !m.is_empty() && m.bytes().all(is_token) && !t.is_empty() =>
+                {
+                    (m, t, v)
+                }
+                _ => return Err(ParseError::BadRequestLine(line)),
+            };
+        if version != "HTTP/1.1" && version != "HTTP/1.0" {
+            return Err(if version.starts_with("HTTP/") {
+                ParseError::UnsupportedVersion(version.to_string())
+            } else {
+                ParseError::BadRequestLine(line.clone())
+            });
+        }
+        let (raw_path, path, query) = parse_target(target)?;
+
+        let mut request = Request {
+            method: method.to_string(),
+            target: target.to_string(),
+            raw_path,
+            path,
+            query,
+            version: version.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        request.headers = read_headers(reader)?;
+
+        if request.version == "HTTP/1.1" && request.header("Host").is_none() {
+            return Err(ParseError::MissingHost);
+        }
+        request.body = read_body(reader, &request)?;
+        Ok(Some(request))
+    }
+
+    /// 按名字查找 header，不区分大小写；有多个同名 header 时返回第一个
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 按名字查找查询参数，有多个同名参数时返回第一个
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// RFC 7230 中 token 允许的字符，方法名和 header 名都必须由它们组成
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+// 读取一行，去掉结尾的 CRLF（也接受单独的 LF）；在行首遇到 EOF 时返回 None
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, ParseError> {
+    let mut buf = Vec::new();
+    // 多读一个字节用来判断是否超过了长度限制
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut buf)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if buf.last() != Some(&b'\n') {
+        return Err(if n > MAX_LINE {
+            ParseError::LineTooLong
+        } else {
+            ParseError::UnexpectedEof
+        });
+    }
+    buf.pop();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|e| ParseError::BadHeader(String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
+
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<Vec<(String, String)>, ParseError> {
+    let mut headers = Vec::new();
+    let mut size = 0;
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::UnexpectedEof)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        size += line.len();
+        if size > MAX_HEADERS {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        // 名字和冒号之间不能有空白，也不接受以空白开头的续行（obs-fold）
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) if !name.is_empty() && name.bytes().all(is_token) => (name, value),
+            _ => return Err(ParseError::BadHeader(line)),
+        };
+        headers.push((
+            name.to_string(),
+            value.trim_matches([' ', '\t']).to_string(),
+        ));
+    }
+}
+
+fn read_body<R: BufRead>(reader: &mut R, request: &Request) -> Result<Vec<u8>, ParseError> {
+    let all = |name: &str| -> Vec<&str> {
+        request
+            .headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    };
+    let codings = all("Transfer-Encoding");
+    let lengths = all("Content-Length");
+    // 前后的代理可能各自只看其中一个 header，对 body 长度的理解就会不同，这是请求走私的常见手段，直接拒绝
+    if codings.len() > 1 || (!codings.is_empty() && !lengths.is_empty()) {
+        let mut headers = codings.clone();
+        headers.extend(&lengths);
+        return Err(ParseError::AmbiguousLength(headers.join(", ")));
+    }
+    match codings.first() {
+        None => {}
+        Some(coding) if coding.eq_ignore_ascii_case("chunked") => return read_chunked(reader),
+        Some(coding) => return Err(ParseError::UnsupportedEncoding(coding.to_string())),
+    }
+
+    let length = match lengths.first() {
+        None => return Ok(Vec::new()),
+        Some(value) => parse_length(value)?,
+    };
+    if lengths.iter().any(|value| *value != lengths[0]) {
+        return Err(ParseError::BadContentLength(lengths.join(", ")));
+    }
+    if length > MAX_BODY {
+        return Err(ParseError::BodyTooLarge);
+    }
+    read_exact(reader, length)
+}
+
+fn parse_length(value: &str) -> Result<usize, ParseError> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::BadContentLength(value.to_string()));
+    }
+    value
+        .parse()
+        .map_err(|_| ParseError::BadContentLength(value.to_string()))
+}
+
+// 长度来自客户端，不预先分配，缓冲区随着真正读到的数据增长
+fn read_exact<R: BufRead>(reader: &mut R, length: usize) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    reader.by_ref().take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(ParseError::UnexpectedEof);
+    }
+    Ok(body)
+}
+
+// chunked 编码：每块是十六进制长度（可以带 ;扩展）、CRLF、数据、CRLF，长度为 0 的块之后是可选的 trailer
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::UnexpectedEof)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let bad = || ParseError::BadChunk(format!("invalid chunk size {:?}", line));
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(bad());
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| bad())?;
+        if size > MAX_BODY - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+        if size == 0 {
+            // trailer 和 header 的格式一样，这里读完后丢弃
+            read_headers(reader)?;
+            return Ok(body);
+        }
+        body.extend(read_exact(reader, size)?);
+        match read_line(reader)? {
+            Some(line) if line.is_empty() => {}
+            _ => {
+                return Err(ParseError::BadChunk(
+                    "missing CRLF after chunk data".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+// 原样的路径、解码后的路径和解码后的查询参数
+type Target = (String, String, Vec<(String, String)>);
+
+// 请求目标可以是 origin-form（/path?query）、absolute-form（http://host/path，发给代理时使用）
+// 或者 OPTIONS 使用的 *
+fn parse_target(target: &str) -> Result<Target, ParseError> {
+    let bad = || ParseError::BadTarget(target.to_string());
+    let origin = if target == "*" {
+        return Ok((target.to_string(), target.to_string(), Vec::new()));
+    } else if target.starts_with('/') {
+        target
+    } else if let Some(rest) = target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        rest.find('/').map_or("/", |i| &rest[i..])
+    } else {
+        return Err(bad());
+    };
+    // 片段（#）不应该出现在请求里
+    if origin.contains('#') {
+        return Err(bad());
+    }
+
+    let (raw_path, query) = origin.split_once('?').unwrap_or((origin, ""));
+    let path = percent_decode(raw_path, false).ok_or_else(bad)?;
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(name, true)?, percent_decode(value, true)?))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(bad)?;
+    Ok((raw_path.to_string(), path, query))
+}
+
+/// 百分号解码，`plus` 为 true 时把 + 解码为空格（查询参数使用的 form 编码）
+///
+/// %XX 不完整或者解码结果不是合法的 UTF-8 时返回 None
+pub fn percent_decode(s: &str, plus: bool) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' if plus => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn parse(raw: &[u8]) -> Result<Option<Request>, ParseError> {
+        Request::read(&mut &raw[..])
+    }
+
+    // 每次 read 只返回一个字节，模拟分成很多次 TCP 读取到达的请求
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn request_line_and_headers() {
+        let request = parse(b"GET /a%20b/c?q=rust+lang&x=%E4%BD%A0&flag HTTP/1.1\r\nHost: localhost\r\nX-Thing:  v \r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/a%20b/c?q=rust+lang&x=%E4%BD%A0&flag");
+        assert_eq!(request.raw_path, "/a%20b/c");
+        assert_eq!(request.path, "/a b/c");
+        assert_eq!(request.query("q"), Some("rust lang"));
+        assert_eq!(request.query("x"), Some("你"));
+        assert_eq!(request.query("flag"), Some(""));
+        assert_eq!(request.header("x-thing"), Some("v"));
+        assert!(request.body.is_empty());
+        assert!(parse(b"").unwrap().is_none());
+
+        // 编码的 / 只在解码后的路径里变成 /
+        let request = parse(b"GET http://h/files/a%2Fb?x=1 HTTP/1.1\r\nHost: h\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.raw_path, "/files/a%2Fb");
+        assert_eq!(request.path, "/files/a/b");
+    }
+
+    #[test]
+    fn bodies() {
+        let request = parse(b"POST /f HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\nhello")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.body, b"hello");
+
+        let request = parse(b"POST /f HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\nB\r\n, world!!!!\r\n0\r\nTrailer: x\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.body, b"hello, world!!!!");
+    }
+
+    #[test]
+    fn large_and_split_requests() {
+        let mut raw = b"POST /upload HTTP/1.1\r\nHost: h\r\n".to_vec();
+        for i in 0..50 {
+            raw.extend(format!("X-Header-{}: {}\r\n", i, "v".repeat(20)).as_bytes());
+        }
+        raw.extend(b"Content-Length: 2000\r\n\r\n");
+        raw.extend(vec![b'x'; 2000]);
+        assert!(raw.len() > 512);
+
+        let mut reader = BufReader::with_capacity(16, Trickle(&raw));
+        let request = Request::read(&mut reader).unwrap().unwrap();
+        assert_eq!(request.headers.len(), 52);
+        assert_eq!(request.body.len(), 2000);
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let cases: [(&[u8], u16); 13] = [
+            (b"GET /\r\n\r\n", 400),
+            (b"GET / HTTP/1.1\r\n\r\n", 400),
+            (b"GET / HTTP/2.0\r\nHost: h\r\n\r\n", 505),
+            (b"GET nowhere HTTP/1.1\r\nHost: h\r\n\r\n", 400),
+            (b"GET /%zz HTTP/1.1\r\nHost: h\r\n\r\n", 400),
+            (b"GET / HTTP/1.1\r\nHost h\r\n\r\n", 400),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: -1\r\n\r\n",
+                400,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 10\r\n\r\nshort",
+                400,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+                400,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: gzip\r\n\r\n",
+                501,
+            ),
+            // 请求走私：多个 Transfer-Encoding，或者同时有 Content-Length
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n0\r\n\r\n",
+                400,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: gzip\r\nContent-Length: 5\r\n\r\nhello",
+                400,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+                400,
+            ),
+        ];
+        for (raw, status) in cases.iter() {
+            let error = parse(raw).unwrap_err();
+            assert_eq!(
+                error.status(),
+                *status,
+                "{:?}",
+                String::from_utf8_lossy(raw)
+            );
+        }
+
+        let mut long = b"GET /".to_vec();
+        long.extend(vec![b'a'; MAX_LINE]);
+        assert_eq!(parse(&long).unwrap_err().status(), 414);
+        let mut long = b"GET / HTTP/1.1\r\nHost: h\r\nX-Long: ".to_vec();
+        long.extend(vec![b'a'; MAX_LINE]);
+        assert_eq!(parse(&long).unwrap_err().status(), 431);
+    }
+}
@@ -0,0 +1,197 @@
+// HTTP 响应：状态码、header 和 body，写出时总是带上 Content-Length（除非状态码规定不能有 body）
+// body 可以是内存中的字节，也可以是文件中的一段，后者写出时边读边写，不需要整个读进内存
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+#[derive(Debug)]
+pub enum Body {
+    Bytes(Vec<u8>),
+    // 文件、开始的偏移和长度
+    File(File, u64, u64),
+}
+
+impl Body {
+    /// body 的字节数，也就是 Content-Length
+    pub fn len(&self) -> u64 {
+        match self {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File(_, _, len) => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 内存中的 body，文件 body 返回 None
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::File(..) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response {
+    /// 创建没有 header、body 为空的响应
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    /// 添加一个 header
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// 设置 body
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Response {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// 以文件中从 offset 开始的 len 个字节作为 body
+    pub fn file(mut self, file: File, offset: u64, len: u64) -> Response {
+        self.body = Body::File(file, offset, len);
+        self
+    }
+
+    /// 按名字查找 header，不区分大小写
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 设置 header，替换掉所有同名的 header
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// 写出状态行、header 和 body
+    ///
+    /// Content-Length 由 body 的长度决定，headers 中的同名 header 会被忽略
+    ///
+    /// 文件 body 在写出时才读取，所以需要 `&mut self`
+    pub fn write_to<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        self.write_head(out)?;
+        if has_body(self.status) {
+            match &mut self.body {
+                Body::Bytes(bytes) => out.write_all(bytes)?,
+                Body::File(file, offset, len) => {
+                    file.seek(SeekFrom::Start(*offset))?;
+                    let copied = io::copy(&mut file.take(*len), out)?;
+                    // 文件在这期间变短了，已经发出的 Content-Length 没法更改，只能断开连接
+                    if copied < *len {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "file shrank while it was being sent",
+                        ));
+                    }
+                }
+            }
+        }
+        out.flush()
+    }
+
+    /// 只写出状态行和 header，用于 HEAD 请求
+    ///
+    /// Content-Length 仍然是 body 的长度，和 GET 得到的一致
+    pub fn write_head<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        if has_body(self.status) {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())
+    }
+}
+
+// 1xx、204 和 304 响应不能有 body，也就不需要 Content-Length
+fn has_body(status: u16) -> bool {
+    !(100..200).contains(&status) && status != 204 && status != 304
+}
+
+/// 状态码对应的原因短语
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_part_of_a_file() {
+        let path = std::env::temp_dir().join(format!("hello-response-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789\x00\xff").unwrap();
+        let mut out = Vec::new();
+        Response::new(206)
+            .file(File::open(&path).unwrap(), 8, 4)
+            .write_to(&mut out)
+            .unwrap();
+        assert!(out.ends_with(b"Content-Length: 4\r\n\r\n89\x00\xff"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_content_length() {
+        let mut out = Vec::new();
+        Response::new(404)
+            .header("Content-Type", "text/plain")
+            .header("Content-Length", "999")
+            .body("missing")
+            .write_to(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 7\r\n\r\nmissing"
+        );
+
+        let mut out = Vec::new();
+        Response::new(204)
+            .body("ignored")
+            .write_to(&mut out)
+            .unwrap();
+        assert_eq!(out, b"HTTP/1.1 204 No Content\r\n\r\n");
+    }
+}
@@ -0,0 +1,264 @@
+// 路由：按方法和路径模式把请求分派给处理函数
+// 路径模式按 / 分段，:name 匹配任意一段，*name 匹配剩下的所有段（只能放在最后），其他段按字面匹配
+// 请求的路径先按原样的 / 分段再逐段解码，所以参数里可以有编码成 %2F 的 /
+// 路由按注册的顺序匹配，先注册的优先；路径匹配但方法不匹配时返回 405 和 Allow，都不匹配时返回 404
+// 没有单独注册 HEAD 时，HEAD 请求交给 GET 的处理函数，由连接只写出响应的 header
+use crate::request::{percent_decode, Request};
+use crate::response::Response;
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+/// 从路径中取出的参数，`:id` 和 `*path` 分别以 `id`、`path` 为名
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// 按名字取出参数
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(String),
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Handler>,
+}
+
+impl Router {
+    /// 创建没有任何路由的 Router
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// 为 method 和路径模式 pattern 注册处理函数
+    ///
+    /// # Panics
+    ///
+    /// pattern 不以 / 开头，或者 `*name` 不在最后一段时会 panic
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        let pattern = parse_pattern(pattern);
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// 注册 GET 请求的处理函数
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    /// 注册 POST 请求的处理函数
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// 替换默认的 404 响应
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// 找到匹配的路由并调用它的处理函数
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            if let Some(params) = match_path(&route.pattern, &request.raw_path) {
+                let head = request.method == "HEAD" && route.method == "GET";
+                if route.method == request.method || head {
+                    return (route.handler)(request, &params);
+                }
+                allowed.push(&route.method);
+                if route.method == "GET" {
+                    allowed.push("HEAD");
+                }
+            }
+        }
+
+        if !allowed.is_empty() {
+            allowed.sort_unstable();
+            allowed.dedup();
+            return Response::new(405)
+                .header("Allow", &allowed.join(", "))
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!(
+                    "{} is not allowed for {}\n",
+                    request.method, request.path
+                ));
+        }
+        match &self.not_found {
+            Some(handler) => handler(request, &Params::default()),
+            None => Response::new(404)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!("{} not found\n", request.path)),
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("路由 {:?} 必须以 / 开头", pattern));
+    let segments: Vec<Segment> = rest
+        .split('/')
+        .map(|s| {
+            if let Some(name) = s.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = s.strip_prefix('*') {
+                Segment::Rest(name.to_string())
+            } else {
+                Segment::Literal(s.to_string())
+            }
+        })
+        .collect();
+    let last = segments.len() - 1;
+    if let Some(i) = segments.iter().position(|s| matches!(s, Segment::Rest(_))) {
+        assert!(i == last, "路由 {:?} 中的 * 只能放在最后一段", pattern);
+    }
+    segments
+}
+
+fn match_path(pattern: &[Segment], raw_path: &str) -> Option<Params> {
+    let mut segments = raw_path.strip_prefix('/')?.split('/');
+    let decode = |s: &str| percent_decode(s, false);
+    let mut params = Vec::new();
+    for expected in pattern {
+        match expected {
+            Segment::Rest(name) => {
+                let rest: Vec<&str> = segments.by_ref().collect();
+                params.push((name.clone(), decode(&rest.join("/"))?));
+            }
+            Segment::Param(name) => match segments.next() {
+                Some(s) if !s.is_empty() => params.push((name.clone(), decode(s)?)),
+                _ => return None,
+            },
+            Segment::Literal(literal) => {
+                if segments.next().and_then(decode).as_ref() != Some(literal) {
+                    return None;
+                }
+            }
+        }
+    }
+    if segments.next().is_some() {
+        return None;
+    }
+    Some(Params(params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, target: &str) -> Request {
+        let raw = format!("{} {} HTTP/1.1\r\nHost: h\r\n\r\n", method, target);
+        Request::read(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_, _| Response::new(200).body("home"))
+            .get("/users/me", |_, _| Response::new(200).body("me"))
+            .get("/users/:id", |_, params| {
+                Response::new(200).body(format!("user {}", params.get("id").unwrap()))
+            })
+            .route("DELETE", "/users/:id", |_, _| Response::new(204))
+            .get("/static/*path", |_, params| {
+                Response::new(200).body(format!("file {:?}", params.get("path").unwrap()))
+            })
+    }
+
+    #[test]
+    fn dispatches_by_path_and_method() {
+        let router = router();
+        assert_eq!(body(&router.handle(&request("GET", "/"))), "home");
+        assert_eq!(body(&router.handle(&request("GET", "/users/me"))), "me");
+        assert_eq!(
+            body(&router.handle(&request("GET", "/users/42?x=1"))),
+            "user 42"
+        );
+        assert_eq!(
+            body(&router.handle(&request("GET", "/users/a%20b"))),
+            "user a b"
+        );
+        assert_eq!(router.handle(&request("DELETE", "/users/42")).status, 204);
+        assert_eq!(
+            body(&router.handle(&request("GET", "/static/css/site.css"))),
+            "file \"css/site.css\""
+        );
+        assert_eq!(
+            body(&router.handle(&request("GET", "/static/"))),
+            "file \"\""
+        );
+    }
+
+    #[test]
+    fn automatic_404_and_405() {
+        let router = router();
+        for target in ["/users", "/users/", "/users/42/posts", "/nope"] {
+            assert_eq!(
+                router.handle(&request("GET", target)).status,
+                404,
+                "{}",
+                target
+            );
+        }
+
+        let response = router.handle(&request("POST", "/users/42"));
+        assert_eq!(response.status, 405);
+        assert_eq!(response.get_header("Allow"), Some("DELETE, GET, HEAD"));
+        assert_eq!(router.handle(&request("HEAD", "/users/42")).status, 200);
+
+        let router = router.not_found(|_, _| Response::new(404).body("custom"));
+        assert_eq!(body(&router.handle(&request("GET", "/nope"))), "custom");
+    }
+
+    #[test]
+    fn encoded_slash_stays_in_param() {
+        let router = router();
+        assert_eq!(
+            body(&router.handle(&request("GET", "/users/a%2Fb"))),
+            "user a/b"
+        );
+        assert_eq!(body(&router.handle(&request("GET", "/users/m%65"))), "me");
+        assert_eq!(
+            body(&router.handle(&request("GET", "/static/a%2Fb/c"))),
+            "file \"a/b/c\""
+        );
+        assert_eq!(router.handle(&request("GET", "/users/a%2Fb/c")).status, 404);
+    }
+}
@@ -0,0 +1,290 @@
+// 优雅停机：触发之后主线程不再 accept 新连接，已经开始接收的请求照常处理，响应带上 Connection: close
+// 处理过请求、正在等下一个请求的空闲连接立刻关闭读端，让阻塞在 read 上的 worker 马上返回；
+// 还在排队或者还没发来第一个请求的连接不关闭，它们的请求同样会被处理
+// 超过期限还没结束的连接被强制关闭，并打印出它们正在处理的请求
+use crate::request::Request;
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown as Close, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    stopping: AtomicBool,
+    // 监听地址，触发时连接一次来唤醒阻塞在 accept 上的主线程
+    wake: Mutex<Option<SocketAddr>>,
+    connections: Mutex<Connections>,
+    // 有连接结束时通知 wait
+    closed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Connections {
+    next_id: usize,
+    open: HashMap<usize, Connection>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    peer: String,
+    stream: TcpStream,
+    served: usize,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    // 在等下一个请求的第一个字节
+    Idle,
+    // 读到了请求的第一个字节，还没读完，以及开始读的时间
+    Receiving(Instant),
+    // 正在处理的请求和开始处理的时间
+    Handling(String, Instant),
+}
+
+/// 一个被跟踪的连接，drop 时从 Shutdown 中移除
+#[derive(Debug)]
+pub struct Tracked {
+    shutdown: Shutdown,
+    id: usize,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    /// 触发停机时连接一次 listener，让阻塞在 `incoming` 上的循环醒来检查 `is_stopping`
+    pub fn wake_on(&self, listener: &TcpListener) -> io::Result<()> {
+        *self.inner.wake.lock().unwrap() = Some(listener.local_addr()?);
+        Ok(())
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.inner.stopping.load(Ordering::SeqCst)
+    }
+
+    /// 开始停机，重复调用没有效果
+    ///
+    /// 关闭处理过请求的空闲连接的读端，正在接收或处理请求的连接在写完响应之后自己关闭
+    pub fn trigger(&self) {
+        {
+            // 和 Tracked::receive、Tracked::finish 在同一把锁下读写 stopping，连接不会在两者之间错过停机
+            let connections = self.connections();
+            if self.inner.stopping.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            for connection in connections.open.values() {
+                if connection.is_idle() {
+                    let _ = connection.stream.shutdown(Close::Read);
+                }
+            }
+        }
+        if let Some(addr) = *self.inner.wake.lock().unwrap() {
+            let _ = TcpStream::connect(addr);
+        }
+    }
+
+    /// 开始跟踪一个刚 accept 的连接
+    pub fn track(&self, stream: &TcpStream) -> io::Result<Tracked> {
+        let connection = Connection {
+            peer: stream.peer_addr()?.to_string(),
+            stream: stream.try_clone()?,
+            served: 0,
+            state: State::Idle,
+        };
+        let mut connections = self.connections();
+        let id = connections.next_id;
+        connections.next_id += 1;
+        connections.open.insert(id, connection);
+        Ok(Tracked {
+            shutdown: self.clone(),
+            id,
+        })
+    }
+
+    /// 还没结束的连接数
+    pub fn open(&self) -> usize {
+        self.connections().open.len()
+    }
+
+    /// 等待所有连接结束，最多等 timeout
+    ///
+    /// 到期后强制关闭剩下的连接，把它们打印到 stderr，返回关闭的连接数
+    pub fn wait(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let mut connections = self.connections();
+        while !connections.open.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            connections = self
+                .inner
+                .closed
+                .wait_timeout(connections, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        for connection in connections.open.values() {
+            match &connection.state {
+                State::Handling(request, started) => eprintln!(
+                    "Dropped connection from {} after {} request(s), in the middle of {} ({:.1?})",
+                    connection.peer,
+                    connection.served,
+                    request,
+                    started.elapsed()
+                ),
+                State::Receiving(started) => eprintln!(
+                    "Dropped connection from {} after {} request(s), while receiving a request ({:.1?})",
+                    connection.peer,
+                    connection.served,
+                    started.elapsed()
+                ),
+                State::Idle => eprintln!(
+                    "Dropped idle connection from {} after {} request(s)",
+                    connection.peer, connection.served
+                ),
+            }
+            let _ = connection.stream.shutdown(Close::Both);
+        }
+        connections.open.len()
+    }
+
+    fn connections(&self) -> MutexGuard<'_, Connections> {
+        self.inner.connections.lock().unwrap()
+    }
+}
+
+impl Connection {
+    // 处理过请求、正在等下一个请求，停机时可以直接关闭
+    fn is_idle(&self) -> bool {
+        self.served > 0 && matches!(self.state, State::Idle)
+    }
+}
+
+impl Tracked {
+    /// 即将读取一个请求的第一个字节
+    ///
+    /// 返回 false 表示已经开始停机而这个连接是空闲连接，读端已经关闭，连接应该直接关闭
+    pub fn receive(&self) -> bool {
+        let mut connections = self.shutdown.connections();
+        if let Some(connection) = connections.open.get_mut(&self.id) {
+            if self.shutdown.is_stopping() && connection.is_idle() {
+                return false;
+            }
+            connection.state = State::Receiving(Instant::now());
+        }
+        true
+    }
+
+    /// 读到了一个完整的请求，开始处理
+    pub fn begin(&self, request: &Request) {
+        if let Some(connection) = self.shutdown.connections().open.get_mut(&self.id) {
+            let line = format!("{} {}", request.method, request.target);
+            connection.state = State::Handling(line, Instant::now());
+        }
+    }
+
+    /// 响应已经写完，返回是否已经开始停机，是的话连接应该关闭
+    pub fn finish(&self) -> bool {
+        let mut connections = self.shutdown.connections();
+        if let Some(connection) = connections.open.get_mut(&self.id) {
+            connection.state = State::Idle;
+            connection.served += 1;
+        }
+        self.shutdown.is_stopping()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.shutdown.is_stopping()
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.shutdown.connections().open.remove(&self.id);
+        self.shutdown.inner.closed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    // 返回服务端和客户端两端
+    fn pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn idle_connections_close_and_busy_ones_are_dropped_at_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let shutdown = Shutdown::new();
+        let (idle, _idle_client) = pair(&listener);
+        let (busy, _busy_client) = pair(&listener);
+        let idle_tracked = shutdown.track(&idle).unwrap();
+        let busy_tracked = shutdown.track(&busy).unwrap();
+        // 空闲连接是处理过请求、正在等下一个请求的连接
+        idle_tracked.receive();
+        assert!(!idle_tracked.finish());
+        // 正在接收下一个请求的连接不是空闲连接
+        let (receiving, _receiving_client) = pair(&listener);
+        let receiving_tracked = shutdown.track(&receiving).unwrap();
+        assert!(receiving_tracked.receive());
+        assert!(!receiving_tracked.finish());
+        assert!(receiving_tracked.receive());
+        let request = Request::read(&mut &b"GET /slow HTTP/1.1\r\nHost: h\r\n\r\n"[..])
+            .unwrap()
+            .unwrap();
+        busy_tracked.begin(&request);
+
+        shutdown.trigger();
+        assert!(shutdown.is_stopping());
+        // 空闲连接的读端已经关闭
+        assert_eq!((&idle).read(&mut [0; 8]).unwrap(), 0);
+        // 读端已经关闭的空闲连接不再接收新的请求
+        assert!(!idle_tracked.receive());
+        drop(idle_tracked);
+        receiving
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        assert!((&receiving).read(&mut [0; 8]).is_err());
+        assert_eq!(shutdown.open(), 2);
+
+        assert_eq!(shutdown.wait(Duration::from_millis(50)), 2);
+        assert!(busy_tracked.finish());
+    }
+
+    #[test]
+    fn wait_returns_when_connections_finish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let shutdown = Shutdown::new();
+        shutdown.wake_on(&listener).unwrap();
+        let (server, _client) = pair(&listener);
+        let tracked = shutdown.track(&server).unwrap();
+
+        let started = Instant::now();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            drop(tracked);
+        });
+        shutdown.trigger();
+        // 触发时连接了一次 listener 来唤醒 accept
+        assert!(listener.accept().is_ok());
+        assert_eq!(shutdown.wait(Duration::from_secs(10)), 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        handle.join().unwrap();
+    }
+}
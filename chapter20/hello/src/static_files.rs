@@ -0,0 +1,493 @@
+// 静态文件：把请求路径映射到 root 目录下的文件
+// 拒绝 ..、以 . 开头的隐藏文件，以及通过符号链接指向 root 之外的文件
+// 支持单个范围的 Range 请求（206）、ETag/Last-Modified 条件请求（304），目录下有 index.html 时返回它，
+// 否则在开启了 listing 时列出目录内容
+use crate::request::Request;
+use crate::response::Response;
+use std::convert::TryFrom;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// 扩展名（小写）到 Content-Type 的映射，找不到时是 application/octet-stream
+const MIME_TYPES: [(&str, &str); 24] = [
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("xml", "application/xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("webp", "image/webp"),
+    ("ico", "image/x-icon"),
+    ("pdf", "application/pdf"),
+    ("wasm", "application/wasm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("zip", "application/zip"),
+];
+
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    listing: bool,
+}
+
+impl StaticFiles {
+    /// 以 root 为根目录提供文件，默认不列出目录内容
+    pub fn new<P: AsRef<Path>>(root: P) -> StaticFiles {
+        StaticFiles {
+            root: root.as_ref().to_path_buf(),
+            listing: false,
+        }
+    }
+
+    /// 目录下没有 index.html 时是否列出目录内容
+    pub fn listing(mut self, listing: bool) -> StaticFiles {
+        self.listing = listing;
+        self
+    }
+
+    /// 返回 root 下 path 对应的文件
+    ///
+    /// path 是已经百分号解码、相对于 root 的路径，通常来自路由中的 `*path`
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        match self.try_serve(request, path) {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => text(404, "Not Found\n"),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => text(403, "Forbidden\n"),
+            Err(e) => {
+                eprintln!("Failed to serve {}: {}", path, e);
+                text(500, "Internal Server Error\n")
+            }
+        }
+    }
+
+    fn try_serve(&self, request: &Request, path: &str) -> io::Result<Response> {
+        let full = self.resolve(path)?;
+        let metadata = fs::metadata(&full)?;
+        if !metadata.is_dir() {
+            return file(request, &full, &metadata);
+        }
+
+        // 目录的 URL 必须以 / 结尾，否则页面中的相对链接会指向上一层
+        if !request.path.ends_with('/') {
+            let location = format!("{}/", percent_encode(&request.path));
+            return Ok(Response::new(301)
+                .header("Location", &location)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!("Moved to {}\n", location)));
+        }
+        let index = full.join("index.html");
+        if let Ok(metadata) = fs::metadata(&index) {
+            if metadata.is_file() {
+                return file(request, &index, &metadata);
+            }
+        }
+        if !self.listing {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        list(&request.path, &full)
+    }
+
+    // 把请求路径转换成 root 下的路径，越界时返回 PermissionDenied，隐藏文件当作不存在
+    fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let mut full = self.root.clone();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            if segment == ".." || segment.contains(['\\', '\0']) {
+                return Err(io::ErrorKind::PermissionDenied.into());
+            }
+            if segment.starts_with('.') {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+            full.push(segment);
+        }
+        // 符号链接可能指向 root 之外，比较两者的真实路径
+        let root = self.root.canonicalize()?;
+        if !full.canonicalize()?.starts_with(&root) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        Ok(full)
+    }
+}
+
+fn text(status: u16, body: &str) -> Response {
+    Response::new(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body)
+}
+
+fn file(request: &Request, path: &Path, metadata: &Metadata) -> io::Result<Response> {
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    // 文件大小和修改时间都没变就认为内容没变
+    let etag = format!("\"{:x}-{:x}\"", len, modified.as_nanos());
+    let last_modified = format_http_date(modified.as_secs());
+    let mime = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| MIME_TYPES.iter().find(|(e, _)| e.eq_ignore_ascii_case(ext)))
+        .map_or("application/octet-stream", |(_, mime)| mime);
+
+    let response = Response::new(200)
+        .header("ETag", &etag)
+        .header("Last-Modified", &last_modified)
+        .header("Accept-Ranges", "bytes");
+    if not_modified(request, &etag, modified.as_secs()) {
+        return Ok(Response {
+            status: 304,
+            ..response
+        });
+    }
+    let response = response.header("Content-Type", mime);
+
+    // If-Range 和当前版本不一致时忽略 Range，返回整个文件
+    let range = request.header("Range").filter(|_| {
+        request
+            .header("If-Range")
+            .is_none_or(|tag| tag == etag || tag == last_modified)
+    });
+    let file = File::open(path)?;
+    Ok(match range.map(|range| parse_range(range, len)) {
+        None | Some(Range::Ignored) => response.file(file, 0, len),
+        Some(Range::Unsatisfiable) => Response::new(416)
+            .header("Content-Range", &format!("bytes */{}", len))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body("Range Not Satisfiable\n"),
+        Some(Range::Bytes(start, end)) => Response {
+            status: 206,
+            ..response
+        }
+        .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len))
+        .file(file, start, end - start + 1),
+    })
+}
+
+// If-None-Match 优先于 If-Modified-Since，只有没有 If-None-Match 时才看修改时间
+fn not_modified(request: &Request, etag: &str, modified: u64) -> bool {
+    if let Some(tags) = request.header("If-None-Match") {
+        // 弱比较：W/ 前缀不影响是否相同
+        return tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag);
+    }
+    request
+        .header("If-Modified-Since")
+        .and_then(parse_http_date)
+        .is_some_and(|since| modified <= since)
+}
+
+#[derive(Debug, PartialEq)]
+enum Range {
+    // 包含两端的字节范围
+    Bytes(u64, u64),
+    Unsatisfiable,
+    // 格式不对或者是多个范围，按没有 Range 处理
+    Ignored,
+}
+
+// bytes=0-99、bytes=100- 或者 bytes=-100（最后 100 个字节）
+fn parse_range(value: &str, len: u64) -> Range {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Range::Ignored,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(pair) => pair,
+        None => return Range::Ignored,
+    };
+    let number = |s: &str| {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            None
+        } else {
+            s.parse::<u64>().ok()
+        }
+    };
+    let (start, end) = match (start, end) {
+        ("", suffix) => match number(suffix) {
+            Some(0) => return Range::Unsatisfiable,
+            Some(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            None => return Range::Ignored,
+        },
+        (start, "") => match number(start) {
+            Some(start) => (start, len.saturating_sub(1)),
+            None => return Range::Ignored,
+        },
+        (start, end) => match (number(start), number(end)) {
+            (Some(start), Some(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return Range::Ignored,
+        },
+    };
+    if len == 0 || start >= len {
+        return Range::Unsatisfiable;
+    }
+    Range::Bytes(start, end)
+}
+
+fn list(url: &str, dir: &Path) -> io::Result<Response> {
+    let mut entries: Vec<(String, bool)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.file_type().ok()?.is_dir();
+            Some((name, is_dir))
+        })
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect();
+    // 目录排在文件前面，各自按名字排序
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let title = escape_html(url);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n",
+        title
+    );
+    if url != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for (name, is_dir) in entries {
+        let slash = if is_dir { "/" } else { "" };
+        html.push_str(&format!(
+            "<li><a href=\"{}{}\">{}{}</a></li>\n",
+            percent_encode(&name),
+            slash,
+            escape_html(&name),
+            slash
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(Response::new(200)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(html))
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 除了不需要编码的字符和 / 以外都编码成 %XX
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Unix 时间戳格式化成 HTTP 日期，例如 Sun, 06 Nov 1994 08:49:37 GMT
+fn format_http_date(secs: u64) -> String {
+    let days = secs / 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let rest = secs % 86_400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+// 只接受 format_http_date 使用的 IMF-fixdate 格式，这也是 RFC 7231 要求发送方使用的格式
+fn parse_http_date(s: &str) -> Option<u64> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    if parts.len() != 6 || parts[5] != "GMT" {
+        return None;
+    }
+    let day: u32 = parts[1].parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == parts[2])? as u32 + 1;
+    let year: i64 = parts[3].parse().ok()?;
+    let time: Vec<u64> = parts[4]
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    if time.len() != 3 || day == 0 || day > 31 || time[0] > 23 || time[1] > 59 || time[2] > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86_400 + time[0] * 3600 + time[1] * 60 + time[2])
+}
+
+// 公历日期和 1970-01-01 以来天数的互相转换（Howard Hinnant 的算法）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &str) -> Request {
+        let raw = format!("GET /static/x HTTP/1.1\r\nHost: h\r\n{}\r\n", headers);
+        Request::read(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    // 每个测试用自己的目录，测试结束时删掉
+    fn root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("hello-static-{}-{}", name, std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::create_dir_all(root.join("site")).unwrap();
+        fs::write(root.join("data.bin"), [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        fs::write(root.join("docs/a b.txt"), "a").unwrap();
+        fs::write(root.join("site/index.html"), "<h1>site</h1>").unwrap();
+        fs::write(root.join(".secret"), "no").unwrap();
+        root
+    }
+
+    #[test]
+    fn http_dates() {
+        assert_eq!(
+            format_http_date(784_111_777),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-3", 10), Range::Bytes(0, 3));
+        assert_eq!(parse_range("bytes=7-", 10), Range::Bytes(7, 9));
+        assert_eq!(parse_range("bytes=-4", 10), Range::Bytes(6, 9));
+        assert_eq!(parse_range("bytes=5-100", 10), Range::Bytes(5, 9));
+        assert_eq!(parse_range("bytes=10-", 10), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), Range::Ignored);
+        assert_eq!(parse_range("lines=1-2", 10), Range::Ignored);
+    }
+
+    #[test]
+    fn serves_files_with_ranges_and_validators() {
+        let root = root("validators");
+        let files = StaticFiles::new(&root);
+
+        let response = files.serve(&request(""), "data.bin");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.len(), 10);
+        assert_eq!(
+            response.get_header("Content-Type"),
+            Some("application/octet-stream")
+        );
+        let etag = response.get_header("ETag").unwrap().to_string();
+        let modified = response.get_header("Last-Modified").unwrap().to_string();
+
+        let response = files.serve(&request("Range: bytes=2-4\r\n"), "data.bin");
+        assert_eq!(response.status, 206);
+        assert_eq!(response.get_header("Content-Range"), Some("bytes 2-4/10"));
+        let mut out = Vec::new();
+        let mut response = response;
+        response.write_to(&mut out).unwrap();
+        assert!(out.ends_with(b"\r\n\r\n\x02\x03\x04"));
+
+        let stale = "If-Range: \"old\"\r\nRange: bytes=2-4\r\n";
+        assert_eq!(files.serve(&request(stale), "data.bin").status, 200);
+        assert_eq!(
+            files
+                .serve(&request("Range: bytes=20-\r\n"), "data.bin")
+                .status,
+            416
+        );
+
+        let cached = format!("If-None-Match: {}\r\n", etag);
+        assert_eq!(files.serve(&request(&cached), "data.bin").status, 304);
+        let cached = format!("If-Modified-Since: {}\r\n", modified);
+        assert_eq!(files.serve(&request(&cached), "data.bin").status, 304);
+        let stale = "If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n";
+        assert_eq!(files.serve(&request(stale), "data.bin").status, 200);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_traversal_and_hidden_files() {
+        let root = root("traversal");
+        let files = StaticFiles::new(root.join("docs"));
+        assert_eq!(files.serve(&request(""), "a b.txt").status, 200);
+        assert_eq!(files.serve(&request(""), "../data.bin").status, 403);
+        assert_eq!(files.serve(&request(""), "..\\data.bin").status, 403);
+        assert_eq!(files.serve(&request(""), "../../etc/passwd").status, 403);
+        assert_eq!(
+            StaticFiles::new(&root)
+                .serve(&request(""), ".secret")
+                .status,
+            404
+        );
+        assert_eq!(files.serve(&request(""), "missing").status, 404);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn directories() {
+        let root = root("directories");
+        let dir = |path: &str| {
+            let raw = format!("GET {} HTTP/1.1\r\nHost: h\r\n\r\n", path);
+            Request::read(&mut raw.as_bytes()).unwrap().unwrap()
+        };
+        let files = StaticFiles::new(&root);
+        let response = files.serve(&dir("/static/site"), "site");
+        assert_eq!(response.status, 301);
+        assert_eq!(response.get_header("Location"), Some("/static/site/"));
+        assert_eq!(files.serve(&dir("/static/site/"), "site").body.len(), 13);
+        assert_eq!(files.serve(&dir("/static/docs/"), "docs").status, 404);
+
+        let response = files.listing(true).serve(&dir("/static/docs/"), "docs");
+        let html = std::str::from_utf8(response.body.as_bytes().unwrap()).unwrap();
+        assert!(html.contains("<a href=\"a%20b.txt\">a b.txt</a>"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}